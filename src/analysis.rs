@@ -0,0 +1,140 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::BlockTree;
+use crate::events::{Event, Record};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MinerQuality {
+    pub id: u8,
+    // share of the canonical chain this miner produced
+    pub share: f64,
+    // what rotation alone would give, 1/N
+    pub expected: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Consistency {
+    pub a: u8,
+    pub b: u8,
+    // over the canonical blocks both have, the gap between the two insertions
+    pub mean_secs: f64,
+    pub max_secs: f64,
+}
+
+/// The usual consensus metrics over the final trees of a run.
+#[derive(Serialize, Debug, Clone)]
+pub struct Analysis {
+    // canonical levels per second of the run
+    pub chain_growth: f64,
+    pub chain_quality: Vec<MinerQuality>,
+    // blocks off the canonical chain over all blocks mined
+    pub stale_rate: f64,
+    // most blocks in a branch that left the canonical chain
+    pub longest_fork: u64,
+    pub time_to_consistency: Vec<Consistency>,
+}
+
+/// The chain of the tip most nodes ended on, the higher one on a tie.
+pub fn canonical_chain(trees: &[&BlockTree]) -> Vec<SealedBlock> {
+    let mut votes: HashMap<BlockHash, (usize, u64, u8)> = HashMap::new();
+    for tree in trees {
+        let vote = votes.entry(tree.tip.digest()).or_insert((0, tree.tip.number, tree.id));
+        vote.0 += 1;
+    }
+    let winner = votes.values().max_by_key(|(count, height, id)| (*count, *height, std::cmp::Reverse(*id)));
+    match winner {
+        Some((_, _, id)) => trees.iter().find(|tree| tree.id == *id).unwrap().chain(),
+        None => vec![],
+    }
+}
+
+impl Analysis {
+    /// `records` only need the `BlockInserted` events, `duration_secs` is how long the run took.
+    pub fn new(trees: &[&BlockTree], records: &[Record], duration_secs: f64) -> Analysis {
+        let canonical = canonical_chain(trees);
+        let canonical_digests: HashSet<BlockHash> = canonical.iter().map(SealedBlock::digest).collect();
+        // every mined block any node has
+        let mut blocks: HashMap<BlockHash, &SealedBlock> = HashMap::new();
+        for tree in trees {
+            for block in tree.number_block.values().flat_map(|blocks| blocks.iter()) {
+                if block.number > 0 {
+                    blocks.insert(block.digest(), block);
+                }
+            }
+        }
+
+        let height = canonical.first().map_or(0, |tip| tip.number);
+        let chain_growth = if duration_secs > 0.0 { height as f64 / duration_secs } else { 0.0 };
+
+        let n = trees.len();
+        let chain_quality = trees.iter().map(|tree| {
+            let mined = canonical.iter().filter(|block| block.number > 0 && block.miner == tree.id).count();
+            MinerQuality {
+                id: tree.id,
+                share: if height > 0 { mined as f64 / height as f64 } else { 0.0 },
+                expected: 1.0 / n as f64,
+            }
+        }).collect();
+
+        let stale = blocks.keys().filter(|digest| !canonical_digests.contains(*digest)).count();
+        let stale_rate = if blocks.is_empty() { 0.0 } else { stale as f64 / blocks.len() as f64 };
+
+        // walk every stale block back to the canonical chain
+        let mut longest_fork = 0;
+        for (digest, block) in blocks.iter() {
+            if canonical_digests.contains(digest) {
+                continue;
+            }
+            let mut length = 1;
+            let mut parent = &block.parent;
+            while let Some(block) = blocks.get(parent) {
+                if canonical_digests.contains(parent) {
+                    break;
+                }
+                length += 1;
+                parent = &block.parent;
+            }
+            longest_fork = longest_fork.max(length);
+        }
+
+        Analysis {
+            chain_growth,
+            chain_quality,
+            stale_rate,
+            longest_fork,
+            time_to_consistency: time_to_consistency(records, &canonical_digests),
+        }
+    }
+}
+
+fn time_to_consistency(records: &[Record], canonical: &HashSet<BlockHash>) -> Vec<Consistency> {
+    let canonical: HashSet<String> = canonical.iter().map(hex::encode).collect();
+    // when each node inserted each canonical block
+    let mut inserted: BTreeMap<u8, HashMap<&str, u64>> = BTreeMap::new();
+    for record in records {
+        if let Event::BlockInserted { node, hash, number, block: Some(_), .. } = &record.event {
+            if *number > 0 && canonical.contains(hash) {
+                inserted.entry(*node).or_default().entry(hash).or_insert(record.time_ms);
+            }
+        }
+    }
+    let mut pairs = vec![];
+    for (a, a_times) in inserted.iter() {
+        for (b, b_times) in inserted.range(a + 1..) {
+            let gaps: Vec<u64> = a_times.iter()
+                .filter_map(|(hash, a_time)| b_times.get(hash).map(|b_time| a_time.abs_diff(*b_time)))
+                .collect();
+            if gaps.is_empty() {
+                continue;
+            }
+            pairs.push(Consistency {
+                a: *a,
+                b: *b,
+                mean_secs: gaps.iter().sum::<u64>() as f64 / gaps.len() as f64 / 1000.0,
+                max_secs: *gaps.iter().max().unwrap() as f64 / 1000.0,
+            });
+        }
+    }
+    pairs
+}
@@ -0,0 +1,172 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::SharedTree;
+use crate::transaction::Transaction;
+
+/// A block as the API shows it: hashes hex encoded and its own hash included.
+#[derive(Serialize)]
+pub struct BlockView {
+    pub hash: String,
+    pub parent: String,
+    pub miner: u8,
+    pub number: u64,
+    pub timestamp: u64,
+    pub state_root: String,
+    pub tx_root: String,
+    pub transactions: Vec<Transaction>,
+    // digests of the transactions, as taken by /proof
+    pub tx_ids: Vec<String>,
+}
+
+impl From<&SealedBlock> for BlockView {
+    fn from(block: &SealedBlock) -> Self {
+        Self {
+            hash: hex::encode(block.digest()),
+            parent: hex::encode(block.parent),
+            miner: block.miner,
+            number: block.number,
+            timestamp: block.timestamp,
+            state_root: hex::encode(&block.state_root),
+            tx_root: hex::encode(&block.tx_root),
+            transactions: block.transactions.clone(),
+            tx_ids: block.transactions.iter().map(|tx| hex::encode(tx.digest())).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct NodeView {
+    id: u8,
+    tip: String,
+    height: u64,
+    blocks: usize,
+    reorgs: usize,
+}
+
+#[derive(Serialize)]
+struct ForkBranch {
+    hash: String,
+    miner: u8,
+    nodes: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct Fork {
+    number: u64,
+    blocks: Vec<ForkBranch>,
+}
+
+type Stores = HashMap<u8, SharedTree>;
+
+/// Answer a request under `/api/`. Errors come back as a status code and a message.
+pub fn route(path: &str, params: &HashMap<String, String>, stores: &Stores) -> Result<Value, (u16, String)> {
+    let segments: Vec<&str> = path.trim_start_matches("/api/").trim_end_matches('/').split('/').collect();
+    let value = match segments.as_slice() {
+        ["nodes"] => serde_json::to_value(nodes(stores)),
+        ["nodes", id, "tip"] => {
+            let store = node(stores, id)?;
+            let read = store.load();
+            serde_json::to_value(BlockView::from(&read.tip))
+        }
+        ["nodes", id, "blocks"] => {
+            let store = node(stores, id)?;
+            let from = number_param(params, "from")?.unwrap_or(0);
+            let read = store.load();
+            // nothing is stored above the highest level, however far `to` reaches
+            let top = read.number_block.keys().max().copied().unwrap_or(0);
+            let to = number_param(params, "to")?.unwrap_or(read.tip.number).min(top);
+            if from > to {
+                return Err((400, format!("from {} is above to {}", from, to)));
+            }
+            let blocks: Vec<BlockView> = (from..=to)
+                .filter_map(|level| read.number_block.get(&level))
+                .flat_map(|blocks| blocks.iter().map(BlockView::from))
+                .collect();
+            serde_json::to_value(blocks)
+        }
+        ["nodes", id, "reorgs"] => {
+            let store = node(stores, id)?;
+            let read = store.load();
+            serde_json::to_value(&read.reorgs)
+        }
+        ["blocks", hash] => {
+            let digest = BlockHash::from_hex(hash).ok_or((400, format!("{} is not a hex hash", hash)))?;
+            let mut block = None;
+            let mut holders = vec![];
+            let mut tip_of = vec![];
+            for id in 0..stores.len() as u8 {
+                let read = stores.get(&id).unwrap().load();
+                if let Some(b) = read.get(&digest) {
+                    block.get_or_insert_with(|| BlockView::from(b));
+                    holders.push(id);
+                    if read.tip.digest() == digest {
+                        tip_of.push(id);
+                    }
+                }
+            }
+            let block = block.ok_or((404, format!("block {} not found", hash)))?;
+            Ok(serde_json::json!({
+                "block": block,
+                "nodes": holders,
+                "tip_of": tip_of,
+            }))
+        }
+        ["forks"] => serde_json::to_value(forks(stores)),
+        _ => return Err((404, format!("{} not found", path))),
+    };
+    Ok(value.expect("Json serialize error"))
+}
+
+fn node<'a>(stores: &'a Stores, id: &str) -> Result<&'a SharedTree, (u16, String)> {
+    id.parse::<u8>().ok()
+        .and_then(|id| stores.get(&id))
+        .ok_or((404, format!("node {} not found", id)))
+}
+
+fn number_param(params: &HashMap<String, String>, name: &str) -> Result<Option<u64>, (u16, String)> {
+    match params.get(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| (400, format!("{} should be a block number", name))),
+        None => Ok(None),
+    }
+}
+
+fn nodes(stores: &Stores) -> Vec<NodeView> {
+    (0..stores.len() as u8).map(|id| {
+        let read = stores.get(&id).unwrap().load();
+        NodeView {
+            id,
+            tip: hex::encode(read.tip.digest()),
+            height: read.tip.number,
+            blocks: read.numbers.len(),
+            reorgs: read.reorgs.len(),
+        }
+    }).collect()
+}
+
+/// Every level where the nodes together know more than one block, with who has which.
+fn forks(stores: &Stores) -> Vec<Fork> {
+    let mut levels: BTreeMap<u64, BTreeMap<BlockHash, (u8, Vec<u8>)>> = BTreeMap::new();
+    for id in 0..stores.len() as u8 {
+        let read = stores.get(&id).unwrap().load();
+        for (number, blocks) in read.number_block.iter() {
+            for block in blocks.iter() {
+                let (_, holders) = levels.entry(*number).or_default()
+                    .entry(block.digest()).or_insert_with(|| (block.miner, vec![]));
+                holders.push(id);
+            }
+        }
+    }
+    levels.into_iter()
+        .filter(|(_, blocks)| blocks.len() > 1)
+        .map(|(number, blocks)| Fork {
+            number,
+            blocks: blocks.into_iter().map(|(hash, (miner, nodes))| ForkBranch {
+                hash: hex::encode(hash),
+                miner,
+                nodes,
+            }).collect(),
+        })
+        .collect()
+}
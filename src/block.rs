@@ -1,46 +1,206 @@
-use serde::{Serialize, Deserialize};
-use std::time::{SystemTime, UNIX_EPOCH};
-use std::fmt::{Display, Formatter, Result};
+use serde::{de, Serialize, Serializer, Deserialize, Deserializer};
+use std::borrow::Borrow;
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display, Formatter, Result};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+use crate::transaction::Transaction;
+use crate::state::State;
+use crate::merkle::{MerkleTree, Proof};
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+
+/// SHA-256 of a header's canonical encoding, see `Header::canonical_bytes`.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct BlockHash(pub [u8; 32]);
+
+impl BlockHash {
+    pub fn from_hex(hex: &str) -> Option<BlockHash> {
+        BlockHash::try_from(hex::decode(hex).ok()?.as_slice()).ok()
+    }
+}
+
+impl AsRef<[u8]> for BlockHash {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl TryFrom<&[u8]> for BlockHash {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(bytes: &[u8]) -> std::result::Result<BlockHash, Self::Error> {
+        Ok(BlockHash(<[u8; 32]>::try_from(bytes)?))
+    }
+}
+
+impl Display for BlockHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl Debug for BlockHash {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "BlockHash({})", self)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, Hash, Eq, PartialEq)]
 pub struct Block {
     pub miner: u8,
     pub number: u64,
     pub timestamp: u64,
-    pub parent: Vec<u8>,
-    // fake for now
+    // all zeros for genesis
+    pub parent: BlockHash,
+    // root of the account state after applying this block
+    pub state_root: Vec<u8>,
+    // merkle root of the transaction hashes
+    pub tx_root: Vec<u8>,
+    pub transactions: Vec<Transaction>,
+    // ed25519 signature of the miner over the header
     pub creator_signature: Vec<u8>,
     // fake for now
     pub verifier_signature: Option<Vec<u8>>,
 }
 
+/// Everything in a `Block` but the transactions. The block hash only covers the header, so a node
+/// holding headers alone can follow parent links.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Header {
+    pub miner: u8,
+    pub number: u64,
+    pub timestamp: u64,
+    pub parent: BlockHash,
+    pub state_root: Vec<u8>,
+    pub tx_root: Vec<u8>,
+    pub creator_signature: Vec<u8>,
+    pub verifier_signature: Option<Vec<u8>>,
+}
+
+/// Miner keys are derived from the miner id so every node knows every public key without a
+/// registry. Not secure, but enough to tell a forged header from a real one.
+pub fn miner_key(miner: u8) -> Ed25519KeyPair {
+    Ed25519KeyPair::from_seed_unchecked(&[miner.wrapping_add(1); 32]).unwrap()
+}
+
 impl Display for Block {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
-        let parent = hex::encode(&self.parent);
-        if parent.len() >= 4 {
-            write!(f, "miner: {}, parent: {}", self.miner, &parent[..4])
+        if self.number > 0 {
+            write!(f, "miner: {}, parent: {}, txs: {}", self.miner, &self.parent.to_string()[..4], self.transactions.len())
         } else {
             write!(f, "miner: {}, parent: null", self.miner)
         }
     }
 }
 
+impl Display for Header {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        if self.number > 0 {
+            write!(f, "miner: {}, parent: {}", self.miner, &self.parent.to_string()[..4])
+        } else {
+            write!(f, "miner: {}, parent: null", self.miner)
+        }
+    }
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+impl Header {
+    /// SHA-256 of `canonical_bytes`. The verifier signature isn't covered, so attaching one
+    /// keeps the hash.
+    pub fn digest(&self) -> BlockHash {
+        let digest = ring::digest::digest(&ring::digest::SHA256, &self.canonical_bytes());
+        BlockHash::try_from(digest.as_ref()).unwrap()
+    }
+
+    /// The bytes the miner signs: `miner` (1 byte), `number` and `timestamp` (8 bytes big endian
+    /// each), `parent` (32 bytes), then `state_root` and `tx_root`, each as a 4 byte big endian
+    /// length followed by the bytes.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.miner];
+        out.extend_from_slice(&self.number.to_be_bytes());
+        out.extend_from_slice(&self.timestamp.to_be_bytes());
+        out.extend_from_slice(&self.parent.0);
+        put_bytes(&mut out, &self.state_root);
+        put_bytes(&mut out, &self.tx_root);
+        out
+    }
+
+    /// What the block hash is computed over: `signing_bytes` followed by `creator_signature`,
+    /// length first like the roots.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        let mut out = self.signing_bytes();
+        put_bytes(&mut out, &self.creator_signature);
+        out
+    }
+
+    pub fn verify_signature(&self) -> bool {
+        let key = miner_key(self.miner);
+        let public_key = UnparsedPublicKey::new(&ED25519, key.public_key().as_ref());
+        public_key.verify(&self.signing_bytes(), &self.creator_signature).is_ok()
+    }
+}
+
 impl Block {
-    pub fn digest(&self) -> Vec<u8> {
-        let mut no_verifier_signature = self.clone();
-        no_verifier_signature.verifier_signature = None;
-        let serialized = bincode::serialize(self).unwrap();
-        let digest = ring::digest::digest(&ring::digest::SHA256, &serialized);
-        digest.as_ref().to_vec()
+    pub fn header(&self) -> Header {
+        Header {
+            miner: self.miner,
+            number: self.number,
+            timestamp: self.timestamp,
+            parent: self.parent,
+            state_root: self.state_root.clone(),
+            tx_root: self.tx_root.clone(),
+            creator_signature: self.creator_signature.clone(),
+            verifier_signature: self.verifier_signature.clone(),
+        }
+    }
+
+    pub fn digest(&self) -> BlockHash {
+        self.header().digest()
     }
 
-    pub fn new(miner: u8, parent: &Block) -> Self {
+    pub fn sign(&mut self, key: &Ed25519KeyPair) {
+        self.creator_signature = key.sign(&self.header().signing_bytes()).as_ref().to_vec();
+    }
+
+    pub fn merkle_tree(&self) -> MerkleTree {
+        let leaves: Vec<Vec<u8>> = self.transactions.iter().map(|tx| tx.digest()).collect();
+        MerkleTree::new(&leaves)
+    }
+
+    /// Merkle root computed from the body. For a well-formed block it equals `tx_root`.
+    pub fn merkle_root(&self) -> Vec<u8> {
+        self.merkle_tree().root()
+    }
+
+    /// Inclusion proof for the transaction with the given digest.
+    pub fn merkle_proof(&self, tx_digest: &[u8]) -> Option<(Transaction, Proof)> {
+        let index = self.transactions.iter().position(|tx| tx.digest() == tx_digest)?;
+        let proof = self.merkle_tree().proof(index)?;
+        Some((self.transactions[index].clone(), proof))
+    }
+
+    /// Check a proof against the `tx_root` in this block's header only, so it works on a header
+    /// obtained from somebody else.
+    pub fn verify_proof(&self, tx: &Transaction, proof: &Proof) -> bool {
+        proof.verify(&self.tx_root, &tx.digest())
+    }
+
+    pub fn new(miner: u8, parent: &SealedBlock, transactions: Vec<Transaction>, state_root: Vec<u8>, timestamp: u64) -> Self {
         let number = parent.number +1;
+        let leaves: Vec<Vec<u8>> = transactions.iter().map(|tx| tx.digest()).collect();
         Self {
             miner,
             number,
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+            timestamp,
             parent: parent.digest(),
+            state_root,
+            tx_root: MerkleTree::new(&leaves).root(),
+            transactions,
             creator_signature: vec![],
             verifier_signature: None,
         }
@@ -50,9 +210,106 @@ impl Block {
             miner: 0,
             number: 0,
             timestamp: 10101,
-            parent: vec![],
+            parent: BlockHash::default(),
+            state_root: State::default().root(),
+            tx_root: MerkleTree::new(&[]).root(),
+            transactions: vec![],
             creator_signature: vec![],
             verifier_signature: None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// A block that can't change any more, checked once and with its hash computed once. Trees,
+/// channels and views pass these around so nothing rehashes a block it already has; cloning
+/// shares the block. The transactions are checked against `tx_root`, so the hash covers the
+/// whole block and equality and hashing go by it alone.
+#[derive(Clone, Debug)]
+pub struct SealedBlock {
+    block: Arc<Block>,
+    hash: BlockHash,
+}
+
+impl SealedBlock {
+    /// Seal `block` if its transactions match its `tx_root` and, unless it's a genesis block, its
+    /// miner signed it.
+    pub fn new(block: Block) -> std::result::Result<SealedBlock, String> {
+        let header = block.header();
+        if block.merkle_root() != block.tx_root {
+            return Err(format!("block {}: transactions don't match tx_root", header.digest()));
+        }
+        if block.number > 0 && !header.verify_signature() {
+            return Err(format!("block {}: bad signature of miner {}", header.digest(), block.miner));
+        }
+        let hash = header.digest();
+        Ok(SealedBlock { block: Arc::new(block), hash })
+    }
+
+    pub fn genesis() -> SealedBlock {
+        SealedBlock::new(Block::genesis()).expect("Genesis is a valid block")
+    }
+
+    /// The cached hash; same as `Block::digest` without the work.
+    pub fn digest(&self) -> BlockHash {
+        self.hash
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+}
+
+// genesis, the one block every tree starts from
+impl Default for SealedBlock {
+    fn default() -> SealedBlock {
+        SealedBlock::genesis()
+    }
+}
+
+impl Deref for SealedBlock {
+    type Target = Block;
+
+    fn deref(&self) -> &Block {
+        &self.block
+    }
+}
+
+impl PartialEq for SealedBlock {
+    fn eq(&self, other: &SealedBlock) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Eq for SealedBlock {}
+
+impl Hash for SealedBlock {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.hash.hash(state);
+    }
+}
+
+// so a set of blocks can be looked up by hash
+impl Borrow<BlockHash> for SealedBlock {
+    fn borrow(&self) -> &BlockHash {
+        &self.hash
+    }
+}
+
+impl Display for SealedBlock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        Display::fmt(self.block(), f)
+    }
+}
+
+// on the wire and on disk it's just the block; it's checked and hashed again when read
+impl Serialize for SealedBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.block.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SealedBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<SealedBlock, D::Error> {
+        SealedBlock::new(Block::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
@@ -1,15 +1,152 @@
-use std::collections::{HashMap, HashSet};
-use super::block::Block;
+use arc_swap::ArcSwap;
+use im::{HashMap, HashSet, Vector};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use super::block::{BlockHash, SealedBlock};
+use super::state::State;
+use super::store::BlockStore;
+use super::events::{Event, EventBus};
+use std::io;
+use std::path::Path;
 
 
-#[derive(Default)]
+/// A node's blocks. The maps are persistent (`im`), so a clone is cheap and shares everything
+/// with the original; that's what gets published in a `SharedTree`.
+#[derive(Default, Clone)]
 pub struct BlockTree {
-    pub number_block: HashMap<u64, HashSet<Block>>,
-    pub tip: Block,
+    pub id: u8,
+    pub number_block: HashMap<u64, HashSet<SealedBlock>>,
+    pub tip: SealedBlock,
+    // post-state of every block whose ancestry back to genesis we have, keyed by block digest
+    pub states: HashMap<BlockHash, State>,
+    // level of every block we have, keyed by block digest
+    pub numbers: HashMap<BlockHash, u64>,
+    // every block we have, keyed by block digest
+    pub blocks: HashMap<BlockHash, SealedBlock>,
+    // blocks whose parent we haven't got, by digest
+    pub orphans: HashSet<BlockHash>,
+    // blocks we refused for their state or their ancestry, by digest; their descendants are refused too
+    pub rejected: HashSet<BlockHash>,
+    // every new block is appended here, if the node is persistent
+    pub store: Option<Arc<Mutex<BlockStore>>>,
+    pub events: EventBus,
+    // every time the tip moved to a block that doesn't extend the old tip, oldest first
+    pub reorgs: Vector<Reorg>,
+    // the last tip whose chain we had, while the current one's has a gap; the reorg, if it is one,
+    // is recorded once the gap is filled
+    pending_reorg: Option<SealedBlock>,
+}
+
+/// A switch of the tip to another branch.
+#[derive(Serialize, Debug, Clone)]
+pub struct Reorg {
+    pub old_tip: String,
+    pub new_tip: String,
+    // level of the new tip
+    pub number: u64,
+    pub depth: u64,
+    // the old chain's blocks that are no longer on the tip's chain, old tip first
+    pub abandoned: Vec<String>,
+}
+
+/// A copy of some levels of a `BlockTree`, so pages can be rendered after the lock is released.
+pub struct TreeSnapshot {
+    pub tip: SealedBlock,
+    pub tip_state: Option<State>,
+    pub levels: BTreeMap<u64, HashSet<SealedBlock>>,
+    pub reorgs: Vector<Reorg>,
+}
+
+/// The latest tree its owner (a miner or the engine) published. Readers `load` it without
+/// locking and get a consistent tree for as long as they keep it; the owner never waits on them.
+#[derive(Clone)]
+pub struct SharedTree(Arc<ArcSwap<BlockTree>>);
+
+impl SharedTree {
+    pub fn new(tree: &BlockTree) -> SharedTree {
+        SharedTree(Arc::new(ArcSwap::from_pointee(tree.clone())))
+    }
+
+    pub fn load(&self) -> Arc<BlockTree> {
+        self.0.load_full()
+    }
+
+    pub fn publish(&self, tree: &BlockTree) {
+        self.0.store(Arc::new(tree.clone()));
+    }
 }
 
 impl BlockTree {
-    pub fn insert(&mut self, block: Block) {
+    pub fn new(id: u8, events: EventBus) -> Self {
+        Self {
+            id,
+            events,
+            ..Default::default()
+        }
+    }
+
+    /// Rebuild the tree from the log at `path` and keep appending to it. Blocks are replayed in
+    /// the order they were first inserted, so the tip comes back as it was.
+    pub fn with_store(mut self, path: &Path) -> io::Result<BlockTree> {
+        let (store, blocks) = BlockStore::open(path)?;
+        for block in blocks {
+            let block = SealedBlock::new(block).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            self.insert(block);
+        }
+        self.store = Some(Arc::new(Mutex::new(store)));
+        Ok(self)
+    }
+
+    pub fn insert(&mut self, block: SealedBlock) {
+        let digest = block.digest();
+        if self.numbers.contains_key(&digest) || self.rejected.contains(&digest) {
+            return;
+        }
+        if self.rejected.contains(&block.parent) {
+            self.remove_invalid(block, "parent was rejected");
+            return;
+        }
+        let state = if block.number == 0 {
+            Some(State::default())
+        } else if let Some(parent_state) = self.states.get(&block.parent) {
+            let mut state = parent_state.clone();
+            state.apply_block(&block);
+            if state.root() != block.state_root {
+                // the miner applied the block to a different state, refuse it and anything
+                // that came before it on top of it
+                self.remove_invalid(block, "state root mismatch");
+                return;
+            }
+            Some(state)
+        } else {
+            // parent not here yet, the state is computed once it arrives
+            None
+        };
+        if let Some(store) = self.store.as_ref() {
+            // the store keeps the error and reports it when the simulation stops
+            let _ = store.lock().unwrap().append(&block);
+        }
+        let number = block.number;
+        self.numbers.insert(digest, number);
+        self.blocks.insert(digest, block.clone());
+        if number > 0 && !self.numbers.contains_key(&block.parent) {
+            self.orphans.insert(digest);
+        }
+        if let Some(children) = self.number_block.get(&(number + 1)) {
+            for child in children.iter().filter(|child| child.parent == digest) {
+                self.orphans.remove(&child.digest());
+            }
+        }
+        let mut tip_changed = false;
+        let mut old_tip = None;
+        self.events.publish(Event::BlockInserted {
+            node: self.id,
+            miner: block.miner,
+            hash: hex::encode(digest),
+            number,
+            block: Some(block.clone()),
+        });
         match self.number_block.get_mut(&block.number) {
             Some(v) => {
                 v.insert(block);
@@ -19,10 +156,12 @@ impl BlockTree {
                 if self.number_block.is_empty() {
                     // if I don't have any block
                     self.tip = block.clone();
+                    tip_changed = true;
                 } else {
                     // only update if it's higher than tip
                     if self.tip.number < number {
-                        self.tip = block.clone();
+                        old_tip = Some(std::mem::replace(&mut self.tip, block.clone()));
+                        tip_changed = true;
                     }
                 }
                 let mut v = HashSet::new();
@@ -30,5 +169,187 @@ impl BlockTree {
                 self.number_block.insert(number, v);
             }
         };
+        if tip_changed {
+            self.tip_changed(old_tip);
+        }
+        if let Some(state) = state {
+            self.connect(digest, number, state);
+        }
+        if self.pending_reorg.is_some() && self.chain().last().unwrap().number == 0 {
+            let old_tip = self.pending_reorg.take().unwrap();
+            self.record_reorg(old_tip);
+        }
+    }
+
+    /// Move the tip to `block`, which must be in the tree, whatever the fork choice says; the
+    /// change is published and recorded like any other.
+    pub fn set_tip(&mut self, block: SealedBlock) {
+        if block != self.tip {
+            let old_tip = std::mem::replace(&mut self.tip, block);
+            self.tip_changed(Some(old_tip));
+        }
+    }
+
+    pub fn get(&self, digest: &BlockHash) -> Option<&SealedBlock> {
+        self.blocks.get(digest)
+    }
+
+    /// Copy levels `from..=to` only, so the cost doesn't grow with the length of the chain.
+    pub fn snapshot(&self, from: u64, to: u64) -> TreeSnapshot {
+        TreeSnapshot {
+            tip: self.tip.clone(),
+            tip_state: self.tip_state().cloned(),
+            levels: (from..=to)
+                .filter_map(|level| self.number_block.get(&level).map(|blocks| (level, blocks.clone())))
+                .collect(),
+            reorgs: self.reorgs.clone(),
+        }
+    }
+
+    /// The tip and its ancestors, tip first, as far back as we have them.
+    pub fn chain(&self) -> Vec<SealedBlock> {
+        let mut chain = vec![self.tip.clone()];
+        while let Some(parent) = self.get(&chain.last().unwrap().parent) {
+            chain.push(parent.clone());
+        }
+        chain
+    }
+
+    fn reject(&self, digest: &BlockHash, reason: &str) {
+        self.events.publish(Event::Rejected {
+            node: self.id,
+            hash: hex::encode(digest),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn tip_changed(&mut self, old_tip: Option<SealedBlock>) {
+        let new_tip = hex::encode(self.tip.digest());
+        self.events.publish(Event::TipChanged {
+            node: self.id,
+            hash: new_tip.clone(),
+            number: self.tip.number,
+        });
+        if let Some(old_tip) = old_tip {
+            let old_tip = match self.pending_reorg.take() {
+                Some(pending) => pending,
+                None if self.tip.parent == old_tip.digest() => return,
+                None => old_tip,
+            };
+            // with a gap in the new tip's chain we can't tell yet what it abandons
+            if self.chain().last().unwrap().number > 0 {
+                self.pending_reorg = Some(old_tip);
+            } else {
+                self.record_reorg(old_tip);
+            }
+        }
+    }
+
+    /// Record a reorg if the tip's chain, which goes back to genesis, leaves out `old_tip`.
+    fn record_reorg(&mut self, old_tip: SealedBlock) {
+        let new_tip = hex::encode(self.tip.digest());
+        let old_digest = old_tip.digest();
+        // the old chain's blocks that the new tip's chain doesn't have; below the parent
+        // of the lowest block we have of the new chain we can't tell, so those count as shared
+        let new_chain = self.chain();
+        let lowest = new_chain.last().unwrap();
+        let mut new_digests: HashSet<BlockHash> = new_chain.iter().map(SealedBlock::digest).collect();
+        new_digests.insert(lowest.parent);
+        let lowest = lowest.number.saturating_sub(1);
+        let mut abandoned = vec![];
+        let mut block = Some(&old_tip);
+        while let Some(b) = block.filter(|b| b.number > 0 && b.number >= lowest) {
+            let digest = b.digest();
+            if new_digests.contains(&digest) {
+                break;
+            }
+            abandoned.push(hex::encode(digest));
+            block = self.get(&b.parent);
+        }
+        // the new tip extends the old one after all
+        if abandoned.is_empty() {
+            return;
+        }
+        let reorg = Reorg {
+            old_tip: hex::encode(old_digest),
+            new_tip,
+            number: self.tip.number,
+            depth: abandoned.len() as u64,
+            abandoned,
+        };
+        self.events.publish(Event::Reorg {
+            node: self.id,
+            old_tip: reorg.old_tip.clone(),
+            new_tip: reorg.new_tip.clone(),
+            depth: reorg.depth,
+            abandoned: reorg.abandoned.clone(),
+        });
+        self.reorgs.push_back(reorg);
+    }
+
+    /// State after applying the current tip, if the tip is connected to genesis.
+    pub fn tip_state(&self) -> Option<&State> {
+        self.states.get(&self.tip.digest())
     }
-}
\ No newline at end of file
+
+    /// Record the post-state of a block and apply its descendants that were waiting for it. States
+    /// are kept per block, so when the tip moves to another fork its state is already there,
+    /// applied on top of its own ancestors rather than on top of the abandoned branch.
+    fn connect(&mut self, digest: BlockHash, number: u64, state: State) {
+        let mut pending = vec![(digest, number, state)];
+        let mut invalid = vec![];
+        while let Some((digest, number, state)) = pending.pop() {
+            if let Some(children) = self.number_block.get(&(number + 1)) {
+                for child in children.iter().filter(|child| child.parent == digest) {
+                    let mut child_state = state.clone();
+                    child_state.apply_block(child);
+                    if child_state.root() == child.state_root {
+                        pending.push((child.digest(), child.number, child_state));
+                    } else {
+                        invalid.push(child.clone());
+                    }
+                }
+            }
+            self.states.insert(digest, state);
+        }
+        for block in invalid {
+            self.remove_invalid(block, "state root mismatch");
+        }
+    }
+
+    /// Refuse a block, whether it is in the tree or not, take out everything built on it and move
+    /// the tip off them.
+    fn remove_invalid(&mut self, block: SealedBlock, reason: &str) {
+        self.reject(&block.digest(), reason);
+        let mut pending = vec![block];
+        while let Some(block) = pending.pop() {
+            let digest = block.digest();
+            self.rejected.insert(digest);
+            if let Some(children) = self.number_block.get(&(block.number + 1)) {
+                for child in children.iter().filter(|child| child.parent == digest) {
+                    self.reject(&child.digest(), "parent was rejected");
+                    pending.push(child.clone());
+                }
+            }
+            if let Some(level) = self.number_block.get_mut(&block.number) {
+                level.remove(&digest);
+                if level.is_empty() {
+                    self.number_block.remove(&block.number);
+                }
+            }
+            self.numbers.remove(&digest);
+            self.blocks.remove(&digest);
+            self.orphans.remove(&digest);
+        }
+        if !self.numbers.contains_key(&self.tip.digest()) {
+            // the highest block left, one we can mine on if there's a choice
+            let tip = self.number_block.values()
+                .flat_map(|blocks| blocks.iter())
+                .max_by_key(|block| (block.number, self.states.contains_key(&block.digest()), block.digest()))
+                .cloned()
+                .unwrap_or_default();
+            let old_tip = std::mem::replace(&mut self.tip, tip);
+            self.tip_changed(Some(old_tip));
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use crate::block_tree::SharedTree;
+use crate::history::History;
+use crate::light_client::HeaderTree;
+use crate::metrics::Metrics;
+use crate::simulation::{Config, Simulation, SimulationBuilder};
+
+/// What the controller sends down to the miner, network and engine threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Pause,
+    Resume,
+    // run the next event; only the deterministic engine does anything with it
+    Step,
+}
+
+/// For the threads that only pause: returns how long they were paused, or None if the
+/// controller hung up meanwhile and they should stop.
+pub fn wait_if_paused(control: &Receiver<Command>) -> Option<Duration> {
+    match control.try_recv() {
+        Ok(Command::Pause) => {
+            let paused = Instant::now();
+            loop {
+                match control.recv() {
+                    Ok(Command::Resume) => return Some(paused.elapsed()),
+                    Ok(_) => continue,
+                    Err(_) => return None,
+                }
+            }
+        }
+        Ok(_) | Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => Some(Duration::default()),
+    }
+}
+
+/// The running simulation, shared by the web server and the terminal view so either can
+/// pause, step or replace it.
+pub struct Control {
+    simulation: Mutex<Simulation>,
+    paused: Mutex<bool>,
+    pub history: History,
+    pub metrics: Arc<Metrics>,
+}
+
+impl Control {
+    /// Start the simulation `builder` sets up. The history and the metrics listen before it
+    /// starts, so they have genesis and whatever was restored or imported at start.
+    pub fn start(builder: SimulationBuilder) -> Result<Arc<Control>, Box<dyn Error>> {
+        let history = History::start(builder.event_bus());
+        let metrics = Metrics::start(builder.event_bus());
+        let simulation = builder.start()?;
+        Ok(Arc::new(Control {
+            simulation: Mutex::new(simulation),
+            paused: Mutex::new(false),
+            history,
+            metrics,
+        }))
+    }
+
+    pub fn stores(&self) -> HashMap<u8, SharedTree> {
+        self.simulation.lock().unwrap().stores.clone()
+    }
+
+    pub fn light_stores(&self) -> HashMap<u8, Arc<RwLock<HeaderTree>>> {
+        self.simulation.lock().unwrap().light_stores.clone()
+    }
+
+    pub fn config(&self) -> Config {
+        self.simulation.lock().unwrap().config.clone()
+    }
+
+    pub fn paused(&self) -> bool {
+        *self.paused.lock().unwrap()
+    }
+
+    pub fn pause(&self) {
+        let mut paused = self.paused.lock().unwrap();
+        if !*paused {
+            self.simulation.lock().unwrap().send(Command::Pause);
+            *paused = true;
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut paused = self.paused.lock().unwrap();
+        if *paused {
+            self.simulation.lock().unwrap().send(Command::Resume);
+            *paused = false;
+        }
+    }
+
+    /// Run one event, if the simulation is deterministic.
+    pub fn step(&self) -> Result<(), String> {
+        let simulation = self.simulation.lock().unwrap();
+        if !simulation.config.deterministic {
+            return Err("step needs a deterministic simulation".to_string());
+        }
+        simulation.send(Command::Step);
+        Ok(())
+    }
+
+    /// Stop the simulation for good; its trees stay readable.
+    pub fn stop(&self) -> Result<(), Box<dyn Error>> {
+        let stopped = self.simulation.lock().unwrap().stop();
+        self.metrics.clear_in_flight();
+        stopped
+    }
+
+    /// Stop the simulation and start a new one from genesis with `config`, reporting to the same
+    /// event bus. The history starts over with it.
+    pub fn reset(&self, config: Config) -> Result<(), Box<dyn Error>> {
+        let mut paused = self.paused.lock().unwrap();
+        let mut simulation = self.simulation.lock().unwrap();
+        let stopped = simulation.stop();
+        self.metrics.clear_in_flight();
+        stopped?;
+        // before the new run publishes its genesis
+        self.history.clear();
+        let events = simulation.events.clone();
+        *simulation = Simulation::start(config, events)?;
+        *paused = false;
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::BlockTree;
+
+/// Where two nodes' trees part.
+pub struct Diff {
+    pub a: u8,
+    pub b: u8,
+    pub a_tip: SealedBlock,
+    pub b_tip: SealedBlock,
+    // highest block on both tips' chains, None if the chains don't reach one yet
+    pub ancestor: Option<SealedBlock>,
+    // blocks one node has and the other doesn't, lowest level first
+    pub only_a: Vec<SealedBlock>,
+    pub only_b: Vec<SealedBlock>,
+    // seconds from the first block after the common ancestor to the newest block either node has,
+    // if the tips differ; on the simulation's clock, which is virtual in deterministic mode
+    pub diverged_secs: Option<u64>,
+}
+
+fn unique(tree: &BlockTree, other: &BlockTree) -> Vec<SealedBlock> {
+    let mut blocks: Vec<SealedBlock> = tree.number_block.values()
+        .flat_map(|blocks| blocks.iter())
+        .filter(|block| !other.numbers.contains_key(&block.digest()))
+        .cloned()
+        .collect();
+    blocks.sort_by_key(|block| (block.number, block.miner));
+    blocks
+}
+
+impl Diff {
+    pub fn new(a: &BlockTree, b: &BlockTree) -> Diff {
+        let a_chain = a.chain();
+        let b_chain = b.chain();
+        let a_digests: HashSet<BlockHash> = a_chain.iter().map(SealedBlock::digest).collect();
+        let ancestor = b_chain.iter().find(|block| a_digests.contains(&block.digest())).cloned();
+        let diverged_secs = match &ancestor {
+            Some(ancestor) if a.tip != b.tip => {
+                let forked_at = a_chain.iter().chain(b_chain.iter())
+                    .filter(|block| block.number == ancestor.number + 1)
+                    .map(|block| block.timestamp)
+                    .min();
+                let newest = a.number_block.values().chain(b.number_block.values())
+                    .flat_map(|blocks| blocks.iter())
+                    .map(|block| block.timestamp)
+                    .max();
+                forked_at.zip(newest).map(|(forked_at, newest)| newest.saturating_sub(forked_at))
+            }
+            _ => None,
+        };
+        Diff {
+            a: a.id,
+            b: b.id,
+            a_tip: a.tip.clone(),
+            b_tip: b.tip.clone(),
+            ancestor,
+            only_a: unique(a, b),
+            only_b: unique(b, a),
+            diverged_secs,
+        }
+    }
+}
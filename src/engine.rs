@@ -0,0 +1,218 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use ring::signature::Ed25519KeyPair;
+use crate::block::{self, SealedBlock};
+use crate::block_tree::{BlockTree, SharedTree};
+use crate::control::Command;
+use crate::events::{Event, EventBus};
+use crate::miner;
+
+enum Task {
+    // miner looks at its tip and mines if it's due
+    Wake(u8),
+    Deliver { from: u8, to: u8, block: SealedBlock, sent_ms: u64 },
+}
+
+/// The miners and the network as a discrete event simulation on a virtual clock, run by one
+/// thread: the same config always gives the same blocks in the same order.
+pub struct Engine {
+    pub n: u8,
+    // virtual ms since the epoch; starts at the genesis timestamp
+    pub now_ms: u64,
+    pub block_int_ms: u64,
+    pub my_turn_wait_ms: u64,
+    pub delay: HashMap<(u8,u8), u64>,
+    pub trees: HashMap<u8, BlockTree>,
+    // where each tree is published after it changes
+    pub shared: HashMap<u8, SharedTree>,
+    keys: HashMap<u8, Ed25519KeyPair>,
+    // (time, sequence number) of pending tasks; the sequence breaks ties in scheduling order
+    queue: BinaryHeap<Reverse<(u64, u64)>>,
+    tasks: HashMap<u64, Task>,
+    next_seq: u64,
+    // the one wake-up that counts for each miner, the others are stale
+    wakes: HashMap<u8, u64>,
+    events: EventBus,
+}
+
+impl Engine {
+    pub fn new(trees: HashMap<u8, BlockTree>, delay: HashMap<(u8,u8), u64>, block_int_ms: u64, my_turn_wait_ms: u64, events: EventBus) -> Engine {
+        let genesis = SealedBlock::genesis();
+        let n = trees.len() as u8;
+        let mut engine = Engine {
+            n,
+            now_ms: genesis.timestamp * 1000,
+            block_int_ms,
+            my_turn_wait_ms,
+            delay,
+            shared: trees.iter().map(|(id, tree)| (*id, SharedTree::new(tree))).collect(),
+            trees,
+            keys: (0..n).map(|id| (id, block::miner_key(id))).collect(),
+            queue: BinaryHeap::new(),
+            tasks: HashMap::new(),
+            next_seq: 0,
+            wakes: HashMap::new(),
+            events,
+        };
+        for id in 0..n {
+            engine.insert(id, genesis.clone());
+            engine.wake_when_due(id);
+        }
+        engine
+    }
+
+    fn insert(&mut self, id: u8, block: SealedBlock) {
+        let tree = self.trees.get_mut(&id).unwrap();
+        tree.insert(block);
+        self.shared[&id].publish(tree);
+    }
+
+    fn schedule(&mut self, time: u64, task: Task) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse((time, seq)));
+        self.tasks.insert(seq, task);
+    }
+
+    /// Schedule miner `id` for when it should build on its current tip, replacing any earlier plan.
+    fn wake_when_due(&mut self, id: u8) {
+        let tip = {
+            let tree = &self.trees[&id];
+            // can't build on a tip whose ancestors haven't arrived yet
+            if tree.tip_state().is_none() {
+                self.wakes.remove(&id);
+                return;
+            }
+            tree.tip.clone()
+        };
+        match miner::due_ms(id, self.n, self.block_int_ms, self.my_turn_wait_ms, &tip) {
+            Some(due) => {
+                let time = due.max(self.now_ms);
+                if self.wakes.get(&id) != Some(&time) {
+                    self.wakes.insert(id, time);
+                    self.schedule(time, Task::Wake(id));
+                }
+            }
+            None => {
+                self.wakes.remove(&id);
+            }
+        }
+    }
+
+    fn mine(&mut self, id: u8) {
+        let block = {
+            let tree = &self.trees[&id];
+            let state = tree.tip_state().expect("Woken without a connected tip").clone();
+            miner::new_block(id, self.n, &self.keys[&id], &tree.tip, state, self.now_ms / 1000)
+        };
+        self.insert(id, block.clone());
+        let hash = hex::encode(block.digest());
+        self.events.publish(Event::BlockMined {
+            miner: id,
+            hash: hash.clone(),
+            number: block.number,
+        });
+        for to in (0..self.n).filter(|to| *to != id) {
+            let delay = self.delay.get(&(id, to)).copied().unwrap_or_default();
+            self.events.publish(Event::BlockSent {
+                from: id,
+                to,
+                delay,
+                hash: hash.clone(),
+            });
+            self.schedule(self.now_ms + delay, Task::Deliver { from: id, to, block: block.clone(), sent_ms: self.now_ms });
+        }
+        self.wake_when_due(id);
+    }
+
+    /// Virtual time of the next event, if any is left.
+    pub fn next_time(&self) -> Option<u64> {
+        self.queue.peek().map(|Reverse((time, _))| *time)
+    }
+
+    /// Run the next event and move the clock to it. Returns false if there was nothing to run.
+    pub fn step(&mut self) -> bool {
+        while let Some(Reverse((time, seq))) = self.queue.pop() {
+            let task = self.tasks.remove(&seq).unwrap();
+            match task {
+                Task::Wake(id) => {
+                    if self.wakes.get(&id) != Some(&time) {
+                        continue;
+                    }
+                    self.wakes.remove(&id);
+                    self.now_ms = time;
+                    let tip = self.trees[&id].tip.clone();
+                    match miner::due_ms(id, self.n, self.block_int_ms, self.my_turn_wait_ms, &tip) {
+                        Some(due) if due <= self.now_ms => self.mine(id),
+                        _ => self.wake_when_due(id),
+                    }
+                }
+                Task::Deliver { from, to, block, sent_ms } => {
+                    self.now_ms = time;
+                    let hash = hex::encode(block.digest());
+                    self.insert(to, block);
+                    self.events.publish(Event::BlockDelivered {
+                        from,
+                        to,
+                        delay: time - sent_ms,
+                        hash,
+                    });
+                    self.wake_when_due(to);
+                }
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Run every event up to virtual time `until_ms`.
+    pub fn run_until(&mut self, until_ms: u64) {
+        while self.next_time().map_or(false, |time| time <= until_ms) {
+            self.step();
+        }
+        self.now_ms = self.now_ms.max(until_ms);
+    }
+
+    /// Run in a thread, keeping virtual time in step with the wall clock while not paused.
+    pub fn start(mut self, running: Arc<AtomicBool>, control: Receiver<Command>) -> JoinHandle<()> {
+        std::thread::Builder::new().name(format!("engine")).spawn(move || {
+            let mut paused = false;
+            // a wall clock instant and the virtual time it stands for
+            let mut anchor = (Instant::now(), self.now_ms);
+            while running.load(Ordering::Relaxed) {
+                let wait = match self.next_time() {
+                    Some(time) if !paused => {
+                        let at = anchor.0 + Duration::from_millis(time.saturating_sub(anchor.1));
+                        at.saturating_duration_since(Instant::now()).min(Duration::from_millis(100))
+                    }
+                    _ => Duration::from_millis(100),
+                };
+                match control.recv_timeout(wait) {
+                    Ok(Command::Pause) => paused = true,
+                    Ok(Command::Resume) => {
+                        paused = false;
+                        anchor = (Instant::now(), self.now_ms);
+                    }
+                    Ok(Command::Step) => {
+                        self.step();
+                        anchor = (Instant::now(), self.now_ms);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        let due = self.next_time().map_or(false, |time| {
+                            anchor.0 + Duration::from_millis(time.saturating_sub(anchor.1)) <= Instant::now()
+                        });
+                        if !paused && due {
+                            self.step();
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        }).unwrap()
+    }
+}
@@ -0,0 +1,38 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::thread::JoinHandle;
+use crate::events::{EventBus, Record};
+
+/// Write every event published on `events` to `path`, one json record per line. The file starts
+/// over, since a replay takes all of it as one run. The thread finishes once the bus is closed
+/// and everything before that is written.
+pub fn start(path: &Path, events: &EventBus) -> io::Result<JoinHandle<()>> {
+    let file = File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    let receiver = events.subscribe();
+    std::thread::Builder::new().name(format!("event log")).spawn(move || {
+        for record in receiver {
+            serde_json::to_writer(&mut writer, &record).expect("Json serialize error");
+            writer.write_all(b"\n").expect("Event log write error");
+            // only complete lines reach the file, so a crash loses at most the last event
+            writer.flush().expect("Event log write error");
+        }
+    })
+}
+
+/// Read a log written by `start`. A last line cut short by a crash is skipped.
+pub fn read(path: &Path) -> Result<Vec<Record>, Box<dyn Error>> {
+    let mut records = vec![];
+    let lines: Vec<String> = BufReader::new(File::open(path)?).lines().collect::<io::Result<_>>()?;
+    let last = lines.len().saturating_sub(1);
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(_) if i == last => break,
+            Err(e) => return Err(format!("line {}: {}", i + 1, e).into()),
+        }
+    }
+    Ok(records)
+}
@@ -0,0 +1,135 @@
+use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::block::SealedBlock;
+
+/// Things that happen in the simulation, published as they happen. Hashes are hex encoded.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(tag = "type")]
+pub enum Event {
+    BlockMined { miner: u8, hash: String, number: u64 },
+    // handed to the link, `delay` is the artificial delay configured on it
+    BlockSent { from: u8, to: u8, delay: u64, hash: String },
+    // handed to the receiver, `delay` is how long it actually took in ms
+    BlockDelivered { from: u8, to: u8, delay: u64, hash: String },
+    // `block` is what a replay inserts; light clients only have the header and leave it out
+    BlockInserted { node: u8, miner: u8, hash: String, number: u64, #[serde(default)] block: Option<SealedBlock> },
+    TipChanged { node: u8, hash: String, number: u64 },
+    // the new tip doesn't extend the old one; `depth` blocks of the old chain were abandoned,
+    // `abandoned` lists them old tip first
+    Reorg { node: u8, old_tip: String, new_tip: String, depth: u64, #[serde(default)] abandoned: Vec<String> },
+    Rejected { node: u8, hash: String, reason: String },
+}
+
+/// An event and when it was published, in ms since the unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Record {
+    pub time_ms: u64,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::BlockMined { .. } => "BlockMined",
+            Event::BlockSent { .. } => "BlockSent",
+            Event::BlockDelivered { .. } => "BlockDelivered",
+            Event::BlockInserted { .. } => "BlockInserted",
+            Event::TipChanged { .. } => "TipChanged",
+            Event::Reorg { .. } => "Reorg",
+            Event::Rejected { .. } => "Rejected",
+        }
+    }
+}
+
+type Hook = Arc<Mutex<Box<dyn FnMut(&Record) + Send>>>;
+type Pending = VecDeque<(Vec<Hook>, Record)>;
+
+thread_local! {
+    // set while this thread runs hooks; what they publish meanwhile waits here for its turn
+    static PENDING: RefCell<Option<Pending>> = RefCell::new(None);
+}
+
+/// Fan-out of events to any number of subscribers. Cloning shares the subscriber list, and
+/// subscribers that hung up are dropped on the next publish.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<Record>>>>,
+    // called right away by the thread that publishes
+    hooks: Arc<Mutex<Vec<Hook>>>,
+    // set by `close`, under the subscribers lock
+    closed: Arc<AtomicBool>,
+}
+
+impl EventBus {
+    /// A receiver of everything published from now on. Once the bus is closed it is hung up
+    /// right away.
+    pub fn subscribe(&self) -> Receiver<Record> {
+        let (sender, receiver) = channel();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        if !self.closed.load(Ordering::Relaxed) {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Call `hook` with every record, in the publishing thread. Each hook runs on its own, so a
+    /// slow one only holds up the threads that are waiting for it. What a hook publishes reaches
+    /// the hooks once it returns.
+    pub fn hook(&self, hook: impl FnMut(&Record) + Send + 'static) {
+        self.hooks.lock().unwrap().push(Arc::new(Mutex::new(Box::new(hook))));
+    }
+
+    pub fn publish(&self, event: Event) {
+        // a copy, so the hooks run without the bus locked
+        let hooks = self.hooks.lock().unwrap().clone();
+        let record = {
+            let mut subscribers = self.subscribers.lock().unwrap();
+            if subscribers.is_empty() && hooks.is_empty() {
+                return;
+            }
+            let record = Record {
+                time_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
+                event,
+            };
+            subscribers.retain(|subscriber| subscriber.send(record.clone()).is_ok());
+            record
+        };
+        if !hooks.is_empty() {
+            run_hooks(hooks, record);
+        }
+    }
+
+    /// Hang up on every subscriber, so the threads reading them finish, and drop the hooks.
+    /// Later subscribers are hung up on as well.
+    pub fn close(&self) {
+        self.hooks.lock().unwrap().clear();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        self.closed.store(true, Ordering::Relaxed);
+        subscribers.clear();
+    }
+}
+
+fn run_hooks(hooks: Vec<Hook>, record: Record) {
+    let outermost = PENDING.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let outermost = pending.is_none();
+        pending.get_or_insert_with(VecDeque::new).push_back((hooks, record));
+        outermost
+    });
+    // published from a hook: the call below us runs it
+    if !outermost {
+        return;
+    }
+    while let Some((hooks, record)) = PENDING.with(|pending| pending.borrow_mut().as_mut().unwrap().pop_front()) {
+        for hook in hooks.iter() {
+            (hook.lock().unwrap())(&record);
+        }
+    }
+    PENDING.with(|pending| pending.borrow_mut().take());
+}
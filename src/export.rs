@@ -0,0 +1,110 @@
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::BlockTree;
+
+/// One node's tree: all of its blocks, parents before children, and which one is the tip.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TreeExport {
+    pub id: u8,
+    pub tip: BlockHash,
+    pub blocks: Vec<SealedBlock>,
+}
+
+/// A dump of some or all nodes, plus the delay matrix they ran under.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SimulationExport {
+    pub nodes: Vec<TreeExport>,
+    #[serde(default)]
+    pub delay: Vec<(u8, u8, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Json,
+    Bincode,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "bincode" | "bin" => Some(Format::Bincode),
+            _ => None,
+        }
+    }
+
+    /// `.json` files are json, anything else is bincode.
+    pub fn from_path(path: &Path) -> Format {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => Format::Json,
+            _ => Format::Bincode,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json => serde_json::to_vec_pretty(value)?,
+            Format::Bincode => bincode::serialize(value)?,
+        })
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Box<dyn Error>> {
+        Ok(match self {
+            Format::Json => serde_json::from_slice(bytes)?,
+            Format::Bincode => bincode::deserialize(bytes)?,
+        })
+    }
+}
+
+impl TreeExport {
+    pub fn new(id: u8, tree: &BlockTree) -> Self {
+        let mut levels: Vec<&u64> = tree.number_block.keys().collect();
+        levels.sort_unstable();
+        let blocks = levels.into_iter().flat_map(|level| tree.number_block[level].iter().cloned()).collect();
+        Self {
+            id,
+            tip: tree.tip.digest(),
+            blocks,
+        }
+    }
+
+    /// Insert the exported blocks into `tree` and move its tip to the exported one, unless the
+    /// tree already has something higher.
+    pub fn load_into(&self, tree: &mut BlockTree) {
+        for block in self.blocks.iter() {
+            tree.insert(block.clone());
+        }
+        if let Some(tip) = tree.get(&self.tip).cloned() {
+            if tip.number >= tree.tip.number {
+                tree.set_tip(tip);
+            }
+        }
+    }
+}
+
+impl SimulationExport {
+    /// The tree to start node `id` from. A dump of a single node seeds every node.
+    pub fn tree_for(&self, id: u8) -> Option<&TreeExport> {
+        if let [only] = self.nodes.as_slice() {
+            return Some(only);
+        }
+        self.nodes.iter().find(|tree| tree.id == id)
+    }
+}
+
+pub fn save(path: &Path, export: &SimulationExport) -> Result<(), Box<dyn Error>> {
+    let bytes = Format::from_path(path).encode(export)?;
+    File::create(path)?.write_all(&bytes)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<SimulationExport, Box<dyn Error>> {
+    let mut bytes = vec![];
+    File::open(path)?.read_to_end(&mut bytes)?;
+    Format::from_path(path).decode(&bytes)
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use crate::block_tree::BlockTree;
+use crate::events::{Event, EventBus, Record};
+use crate::replay;
+
+/// Every block insertion since the start of the run, kept in memory so the trees can be rebuilt
+/// as they were at any moment.
+#[derive(Clone, Default)]
+pub struct History {
+    records: Arc<RwLock<Vec<Record>>>,
+}
+
+impl History {
+    pub fn start(events: &EventBus) -> History {
+        let history = History::default();
+        let records = history.records.clone();
+        // recorded by the thread that publishes, so once a run has stopped all of it is here
+        events.hook(move |record| {
+            // the only events a replay needs
+            if let Event::BlockInserted { block: Some(_), .. } = record.event {
+                records.write().unwrap().push(record.clone());
+            }
+        });
+        history
+    }
+
+    /// Forget everything, for a simulation that starts over.
+    pub fn clear(&self) {
+        self.records.write().unwrap().clear();
+    }
+
+    /// Milliseconds between the first and the last recorded insertion.
+    pub fn duration_ms(&self) -> u64 {
+        let records = self.records.read().unwrap();
+        match (records.first(), records.last()) {
+            (Some(first), Some(last)) => last.time_ms - first.time_ms,
+            _ => 0,
+        }
+    }
+
+    /// A copy of what was recorded so far.
+    pub fn records(&self) -> Vec<Record> {
+        self.records.read().unwrap().clone()
+    }
+
+    /// The full nodes' trees `at_ms` after the first insertion.
+    pub fn replay(&self, at_ms: u64) -> BTreeMap<u8, BlockTree> {
+        // replaying takes a while, and the miners wait on the lock to record their events
+        let records = self.records();
+        replay::replay(&records, Some(at_ms))
+    }
+}
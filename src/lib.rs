@@ -0,0 +1,40 @@
+#[macro_use]
+extern crate crossterm;
+#[macro_use]
+extern crate horrorshow;
+
+pub mod analysis;
+pub mod api;
+pub mod merkle;
+pub mod metrics;
+pub mod miner;
+pub mod block;
+pub mod block_tree;
+pub mod control;
+pub mod engine;
+pub mod event_log;
+pub mod events;
+pub mod diff;
+pub mod export;
+pub mod history;
+pub mod light_client;
+pub mod network;
+pub mod observer;
+pub mod replay;
+pub mod report;
+pub mod server;
+pub mod simulation;
+pub mod state;
+pub mod store;
+pub mod svg;
+pub mod tui;
+pub mod sweep;
+pub mod transaction;
+
+pub use block::{Block, BlockHash, SealedBlock};
+pub use block_tree::BlockTree;
+pub use events::{Event, EventBus, Record};
+pub use miner::Miner;
+pub use network::Network;
+pub use observer::SimulationObserver;
+pub use simulation::{Config, Simulation, SimulationBuilder};
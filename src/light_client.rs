@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::block::{BlockHash, Header};
+use crate::events::{Event, EventBus};
+
+/// Header-only counterpart of `BlockTree`. Headers are only added once their signature checks out
+/// and their parent is known; headers that arrive before their parent wait in `orphans`.
+#[derive(Default)]
+pub struct HeaderTree {
+    pub id: u8,
+    pub number_header: HashMap<u64, HashSet<Header>>,
+    pub tip: Header,
+    // headers waiting for their parent, keyed by the parent digest
+    pub orphans: HashMap<BlockHash, Vec<Header>>,
+    // level of every accepted header, keyed by header digest
+    pub numbers: HashMap<BlockHash, u64>,
+    // when each header first arrived, counting from 0, keyed by header digest
+    pub arrivals: HashMap<BlockHash, u64>,
+    pub events: EventBus,
+}
+
+impl HeaderTree {
+    pub fn insert(&mut self, header: Header) {
+        let arrival = self.arrivals.len() as u64;
+        self.arrivals.entry(header.digest()).or_insert(arrival);
+        if header.number == 0 {
+            // genesis is not signed, it is the one header everybody agrees on
+            if header.parent != BlockHash::default() {
+                return;
+            }
+        } else if !header.verify_signature() {
+            self.reject(&header, "bad signature");
+            return;
+        } else {
+            match self.numbers.get(&header.parent) {
+                Some(parent_number) if parent_number + 1 == header.number => {}
+                Some(_) => {
+                    self.reject(&header, "number is not parent + 1");
+                    return;
+                }
+                None => {
+                    self.orphans.entry(header.parent).or_default().push(header);
+                    return;
+                }
+            }
+        }
+        let mut pending = VecDeque::from(vec![header]);
+        while let Some(header) = pending.pop_front() {
+            let digest = header.digest();
+            if let Some(children) = self.orphans.remove(&digest) {
+                pending.extend(children.into_iter().filter(|child| child.number == header.number + 1));
+            }
+            self.accept(digest, header);
+        }
+    }
+
+    fn reject(&self, header: &Header, reason: &str) {
+        self.events.publish(Event::Rejected {
+            node: self.id,
+            hash: hex::encode(header.digest()),
+            reason: reason.to_string(),
+        });
+    }
+
+    /// Copy of levels `from..=to`.
+    pub fn snapshot(&self, from: u64, to: u64) -> BTreeMap<u64, HashSet<Header>> {
+        (from..=to)
+            .filter_map(|level| self.number_header.get(&level).map(|headers| (level, headers.clone())))
+            .collect()
+    }
+
+    /// Same fork choice as `BlockTree::insert`: the tip is the highest header, the first to arrive
+    /// if there are several. Orphans count from when they arrived, not from when they were accepted.
+    fn accept(&mut self, digest: BlockHash, header: Header) {
+        self.events.publish(Event::BlockInserted {
+            node: self.id,
+            miner: header.miner,
+            hash: hex::encode(digest),
+            number: header.number,
+            block: None,
+        });
+        self.numbers.insert(digest, header.number);
+        let first = self.number_header.is_empty()
+            || self.tip.number < header.number
+            || (self.tip.number == header.number && self.arrivals[&digest] < self.arrivals[&self.tip.digest()]);
+        if first {
+            self.tip = header.clone();
+        }
+        self.number_header.entry(header.number).or_default().insert(header);
+    }
+}
+
+/// A node that only receives headers from the network and never mines or relays.
+pub struct LightClient {
+    id: u8,
+    header_tree: Arc<RwLock<HeaderTree>>,
+    from_network: Receiver<Header>,
+    running: Arc<AtomicBool>,
+}
+
+impl LightClient {
+    pub fn new(id: u8, events: EventBus, from_network: Receiver<Header>, running: Arc<AtomicBool>) -> (LightClient, Arc<RwLock<HeaderTree>>) {
+        let header_tree = HeaderTree {
+            id,
+            events,
+            ..Default::default()
+        };
+        let header_tree = Arc::new(RwLock::new(header_tree));
+        let ht_clone = header_tree.clone();
+        let client = LightClient {
+            id,
+            header_tree,
+            from_network,
+            running,
+        };
+        (client, ht_clone)
+    }
+
+    pub fn start(self) -> JoinHandle<()> {
+        std::thread::Builder::new().name(format!("Light client {}", self.id)).spawn(move || self.client_loop()).unwrap()
+    }
+
+    fn client_loop(&self) {
+        while self.running.load(Ordering::Relaxed) {
+            match self.from_network.recv_timeout(Duration::from_millis(100)) {
+                Ok(header) => {
+                    let mut store = self.header_tree.write().unwrap();
+                    store.insert(header);
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+    }
+}
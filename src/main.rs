@@ -1,31 +1,17 @@
-#[macro_use]
-extern crate crossterm;
-#[macro_use]
-extern crate horrorshow;
-
-mod miner;
-mod block;
-mod block_tree;
-mod network;
-mod server;
-
 use crossterm::{cursor};
 use crossterm::terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType, ScrollUp, size};
 use crossterm::style::{Color, SetForegroundColor, Print};
 use std::io::{stdout, Write, BufReader};
 use std::collections::HashMap;
-use crate::miner::Miner;
-use std::sync::mpsc::{channel, Sender};
-use crate::network::Network;
-use crate::block::Block;
-use std::time::{Duration, Instant};
-use std::sync::{Arc, RwLock};
-use crate::block_tree::BlockTree;
+use std::sync::mpsc::{channel, Receiver};
+use crossterm_blockchain_dashboard::{event_log, export, replay, report, server, sweep, tui};
+use crossterm_blockchain_dashboard::control::Control;
+use crossterm_blockchain_dashboard::events::EventBus;
+use crossterm_blockchain_dashboard::simulation::{Config, SimulationBuilder};
 use std::str::FromStr;
 use std::error::Error;
 use std::fs::File;
-
-const N: u8 = 6;
+use std::path::PathBuf;
 
 
 // pub fn draw_block_hash(id: u8, block: &Block) -> Result<()> {
@@ -33,33 +19,136 @@ const N: u8 = 6;
 //     execute!(stdout, cursor::MoveTo(10*id as u16,MARGIN+block.number as u16), Print(format!("{}", &hex::encode(block.digest())[..4])))
 // }
 
-fn start_simulation() -> Result<(Network, HashMap<u8, Arc<RwLock<BlockTree>>>), Box<dyn Error>> {
-    let mut stores = HashMap::new();
-    let (sender, receiver) = channel();
-    let mut senders: HashMap<u8, Sender<Block>> = Default::default();
-    for id in 0..N {
-        let (sender_2, receiver_2) = channel();
-        senders.insert(id, sender_2);
-        let (miner, store) = Miner::new(id, N, sender.clone(), receiver_2);
-        stores.insert(id, store);
-        miner.start();
+#[derive(Default)]
+struct Options {
+    config: Config,
+    // append every event to this file as json lines
+    event_log: Option<PathBuf>,
+    // run without the web server and stop after --duration seconds or --blocks levels
+    headless: bool,
+    duration: Option<f64>,
+    blocks: Option<u64>,
+    // write the summary of a headless run here as json
+    report: Option<PathBuf>,
+    // show the terminal diff view next to the web server
+    tui: bool,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, Box<dyn Error>> {
+    let mut options = Options::default();
+    let mut args = args.iter().cloned();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--data-dir" => {
+                let dir = args.next().ok_or("--data-dir expects a directory")?;
+                options.config.data_dir = Some(PathBuf::from(dir));
+            }
+            "--import" => {
+                let file = args.next().ok_or("--import expects a file")?;
+                options.config.import = Some(PathBuf::from(file));
+            }
+            "--event-log" => {
+                let file = args.next().ok_or("--event-log expects a file")?;
+                options.event_log = Some(PathBuf::from(file));
+            }
+            "--headless" => options.headless = true,
+            "--tui" => options.tui = true,
+            "--deterministic" => options.config.deterministic = true,
+            "--duration" => {
+                let seconds = args.next().ok_or("--duration expects seconds")?;
+                options.duration = Some(seconds.parse()?);
+            }
+            "--blocks" => {
+                let blocks = args.next().ok_or("--blocks expects a number of levels")?;
+                options.blocks = Some(blocks.parse()?);
+            }
+            "--report" => {
+                let file = args.next().ok_or("--report expects a file")?;
+                options.report = Some(PathBuf::from(file));
+            }
+            "--nodes" => {
+                let nodes = args.next().ok_or("--nodes expects a number")?;
+                options.config.nodes = Some(nodes.parse()?);
+            }
+            "--block-int-ms" => {
+                let ms = args.next().ok_or("--block-int-ms expects milliseconds")?;
+                options.config.block_int_ms = Some(ms.parse()?);
+            }
+            "--turn-wait-ms" => {
+                let ms = args.next().ok_or("--turn-wait-ms expects milliseconds")?;
+                options.config.my_turn_wait_ms = Some(ms.parse()?);
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
     }
-    let log = Arc::new(RwLock::new(String::new()));
-    let network = Network {
-        n: N,
-        from_miners: receiver,
-        senders,
-        artificial_delay: Default::default(),
-        log: log.clone(),
+    Ok(options)
+}
+
+fn run_headless(options: Options, shutdown: Receiver<()>) -> Result<(), Box<dyn Error>> {
+    let events = EventBus::default();
+    let event_log = match &options.event_log {
+        Some(path) => Some(event_log::start(path, &events)?),
+        None => None,
     };
-    Ok((network, stores))
+    let report = report::run_batch(options.config, events.clone(), options.duration, options.blocks, Some(&shutdown));
+    events.close();
+    if let Some(event_log) = event_log {
+        event_log.join().expect("Event log thread panicked");
+    }
+    let report = report?;
+    report.print();
+    if let Some(path) = &options.report {
+        std::fs::write(path, serde_json::to_string_pretty(&report).expect("Json serialize error"))?;
+    }
+    Ok(())
+}
+
+/// `replay <log> [--at <seconds>] [--export <file>]`: rebuild the trees recorded in an event log
+/// as they were `--at` seconds into the run, print their tips and optionally export them.
+fn replay_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut args = args.iter();
+    let log = args.next().ok_or("replay expects an event log")?;
+    let mut at = None;
+    let mut export_to = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--at" => {
+                let seconds: f64 = args.next().ok_or("--at expects seconds")?.parse()?;
+                at = Some((seconds * 1000.0) as u64);
+            }
+            "--export" => {
+                export_to = Some(PathBuf::from(args.next().ok_or("--export expects a file")?));
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+    let records = event_log::read(log.as_ref())?;
+    let trees = replay::replay(&records, at);
+    for (id, tree) in trees.iter() {
+        println!("node{}: tip {} at level {}, {} blocks", id, &hex::encode(tree.tip.digest())[..4], tree.tip.number, tree.numbers.len());
+    }
+    if let Some(path) = export_to {
+        let export = export::SimulationExport {
+            nodes: trees.iter().map(|(id, tree)| export::TreeExport::new(*id, tree)).collect(),
+            delay: vec![],
+        };
+        export::save(&path, &export)?;
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let (mut network, stores) = start_simulation()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("replay") {
+        return replay_command(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("sweep") {
+        return sweep::sweep_command(&args[1..]);
+    }
+    let mut options = parse_options(&args)?;
     let addr = std::net::SocketAddr::from_str("127.0.0.1:3333").expect("Parse address error");
     // Read the JSON contents of the file as an instance of `delay`.
-    let mut delay: HashMap<(u8,u8), u64> = {
+    let delay: HashMap<(u8,u8), u64> = {
         if let Ok(file) = File::open("delay.json") {
             let reader = BufReader::new(file);
             let pretty_delay: Vec<(u8,u8,u64)> = serde_json::from_reader(reader)?;
@@ -73,12 +162,37 @@ fn main() -> Result<(), Box<dyn Error>> {
     //         delay.insert((i,j), 11000);
     //     }
     // }
-    server::Server::start(addr,stores, &delay);
-    network.set_delay(delay);
-    network.start();
-    loop {
-        std::thread::park();
+    options.config.delay = delay;
+    // Ctrl-C stops everything in order instead of killing the threads mid-write
+    let (interrupt, shutdown) = channel();
+    ctrlc::set_handler(move || {
+        let _ = interrupt.send(());
+    })?;
+    if options.headless {
+        return run_headless(options, shutdown);
+    }
+    let events = EventBus::default();
+    let event_log = match &options.event_log {
+        Some(path) => Some(event_log::start(path, &events)?),
+        None => None,
+    };
+    let control = Control::start(SimulationBuilder::new().config(options.config).events(events.clone()))?;
+    let server = server::Server::start(addr, control.clone(), events.clone());
+    let result = if options.tui {
+        tui::run(&control)
+    } else {
+        let _ = shutdown.recv();
+        Ok(())
+    };
+    let stopped = control.stop();
+    // the event log, the history, the metrics and the event streams all end with the bus
+    events.close();
+    if let Some(event_log) = event_log {
+        event_log.join().expect("Event log thread panicked");
     }
+    server.stop();
+    result?;
+    stopped
 }
 /*
 fn _main() -> Result<()> {
@@ -0,0 +1,109 @@
+use serde::{Serialize, Deserialize};
+
+// leaves and inner nodes are hashed apart, so an inner node can't pass for a leaf
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+
+fn hash_leaf(leaf: &[u8]) -> Vec<u8> {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    ctx.update(&[LEAF]);
+    ctx.update(leaf);
+    ctx.finish().as_ref().to_vec()
+}
+
+fn hash_pair(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut ctx = ring::digest::Context::new(&ring::digest::SHA256);
+    ctx.update(&[NODE]);
+    ctx.update(left);
+    ctx.update(right);
+    ctx.finish().as_ref().to_vec()
+}
+
+/// Binary Merkle tree over leaf hashes. A level with an odd number of nodes moves its last node
+/// up as it is, so no two lists of leaves share a root.
+pub struct MerkleTree {
+    // levels[0] are the hashed leaves, the last level holds the root
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+/// Sibling hashes from the leaf up to (but excluding) the root. Levels where the node moved up
+/// without a sibling have none, which is why the proof needs the number of leaves.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct Proof {
+    pub index: usize,
+    pub leaves: usize,
+    pub siblings: Vec<Vec<u8>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: &[Vec<u8>]) -> Self {
+        let mut levels = vec![leaves.iter().map(|leaf| hash_leaf(leaf)).collect::<Vec<_>>()];
+        while levels.last().unwrap().len() > 1 {
+            let level = levels.last().unwrap();
+            let next = level.chunks(2).map(|pair| match pair {
+                [left, right] => hash_pair(left, right),
+                _ => pair[0].clone(),
+            }).collect();
+            levels.push(next);
+        }
+        Self {
+            levels,
+        }
+    }
+
+    /// The root, or 32 zero bytes for an empty tree.
+    pub fn root(&self) -> Vec<u8> {
+        match self.levels.last().unwrap().first() {
+            Some(root) => root.clone(),
+            None => vec![0; 32],
+        }
+    }
+
+    pub fn proof(&self, index: usize) -> Option<Proof> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+        let mut siblings = vec![];
+        let mut i = index;
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(i ^ 1) {
+                siblings.push(sibling.clone());
+            }
+            i /= 2;
+        }
+        Some(Proof {
+            index,
+            leaves: self.levels[0].len(),
+            siblings,
+        })
+    }
+}
+
+impl Proof {
+    /// Check that `leaf` sits at `self.index` of the tree with the given root.
+    pub fn verify(&self, root: &[u8], leaf: &[u8]) -> bool {
+        if self.index >= self.leaves {
+            return false;
+        }
+        let mut hash = hash_leaf(leaf);
+        let mut siblings = self.siblings.iter();
+        let (mut i, mut width) = (self.index, self.leaves);
+        while width > 1 {
+            // the last node of an odd level has no sibling
+            if i % 2 == 1 || i + 1 < width {
+                let sibling = match siblings.next() {
+                    Some(sibling) => sibling,
+                    None => return false,
+                };
+                hash = if i % 2 == 0 {
+                    hash_pair(&hash, sibling)
+                } else {
+                    hash_pair(sibling, &hash)
+                };
+            }
+            i /= 2;
+            width = width / 2 + width % 2;
+        }
+        siblings.next().is_none() && hash == root
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use crate::block_tree::SharedTree;
+use crate::events::{Event, EventBus};
+
+const REORG_DEPTH_BUCKETS: [f64; 7] = [1.0, 2.0, 3.0, 5.0, 8.0, 13.0, 21.0];
+const LATENCY_MS_BUCKETS: [f64; 9] = [10.0, 100.0, 1000.0, 5000.0, 10000.0, 20000.0, 30000.0, 60000.0, 120000.0];
+
+struct Histogram {
+    bounds: &'static [f64],
+    // cumulative, one per bound
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter_mut()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, bucket).unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count).unwrap();
+        writeln!(out, "{}_sum {}", name, self.sum).unwrap();
+        writeln!(out, "{}_count {}", name, self.count).unwrap();
+    }
+}
+
+struct Counters {
+    blocks_mined: BTreeMap<u8, u64>,
+    blocks_received: BTreeMap<u8, u64>,
+    // (from, to, hash) of messages sleeping on a delayed link
+    in_flight: HashSet<(u8, u8, String)>,
+    reorgs: BTreeMap<u8, u64>,
+    reorg_depth: Histogram,
+    delivery_latency: Histogram,
+}
+
+/// Counters fed from the event bus, plus gauges read from the trees when scraped.
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    /// Start counting events from `events`, as they are published.
+    pub fn start(events: &EventBus) -> Arc<Metrics> {
+        let metrics = Arc::new(Metrics {
+            counters: Mutex::new(Counters {
+                blocks_mined: Default::default(),
+                blocks_received: Default::default(),
+                in_flight: Default::default(),
+                reorgs: Default::default(),
+                reorg_depth: Histogram::new(&REORG_DEPTH_BUCKETS),
+                delivery_latency: Histogram::new(&LATENCY_MS_BUCKETS),
+            }),
+        });
+        let collector = metrics.clone();
+        events.hook(move |record| collector.record(&record.event));
+        metrics
+    }
+
+    /// Forget the messages on the links of a simulation that stopped; they are never delivered.
+    pub fn clear_in_flight(&self) {
+        self.counters.lock().unwrap().in_flight.clear();
+    }
+
+    fn record(&self, event: &Event) {
+        let mut counters = self.counters.lock().unwrap();
+        match event {
+            Event::BlockMined { miner, .. } => {
+                *counters.blocks_mined.entry(*miner).or_default() += 1;
+            }
+            Event::BlockSent { from, to, delay, hash } => {
+                if *delay > 0 {
+                    counters.in_flight.insert((*from, *to, hash.clone()));
+                }
+            }
+            Event::BlockDelivered { from, to, delay, hash } => {
+                counters.in_flight.remove(&(*from, *to, hash.clone()));
+                *counters.blocks_received.entry(*to).or_default() += 1;
+                counters.delivery_latency.observe(*delay as f64);
+            }
+            Event::Reorg { node, depth, .. } => {
+                *counters.reorgs.entry(*node).or_default() += 1;
+                counters.reorg_depth.observe(*depth as f64);
+            }
+            Event::BlockInserted { .. } | Event::TipChanged { .. } | Event::Rejected { .. } => {}
+        }
+    }
+
+    /// The Prometheus text format.
+    pub fn render(&self, stores: &HashMap<u8, SharedTree>) -> String {
+        let mut out = String::new();
+        let mut gauges: Vec<(u8, u64, usize, usize)> = vec![];
+        for id in 0..stores.len() as u8 {
+            let read = stores.get(&id).unwrap().load();
+            let forks = read.number_block.values().filter(|blocks| blocks.len() > 1).count();
+            gauges.push((id, read.tip.number, forks, read.orphans.len()));
+        }
+        let counters = self.counters.lock().unwrap();
+        counter_family(&mut out, "blocks_mined_total", "Blocks mined per miner.", "miner", &counters.blocks_mined);
+        counter_family(&mut out, "blocks_received_total", "Blocks delivered to each node by the network.", "node", &counters.blocks_received);
+        counter_family(&mut out, "reorgs_total", "Tip switches to a block that doesn't extend the old tip.", "node", &counters.reorgs);
+        writeln!(out, "# HELP tip_height Level of each node's tip.").unwrap();
+        writeln!(out, "# TYPE tip_height gauge").unwrap();
+        for (id, height, _, _) in gauges.iter() {
+            writeln!(out, "tip_height{{node=\"{}\"}} {}", id, height).unwrap();
+        }
+        writeln!(out, "# HELP fork_levels Levels where a node has more than one block.").unwrap();
+        writeln!(out, "# TYPE fork_levels gauge").unwrap();
+        for (id, _, forks, _) in gauges.iter() {
+            writeln!(out, "fork_levels{{node=\"{}\"}} {}", id, forks).unwrap();
+        }
+        writeln!(out, "# HELP orphan_blocks Blocks a node has whose parent it hasn't got.").unwrap();
+        writeln!(out, "# TYPE orphan_blocks gauge").unwrap();
+        for (id, _, _, orphans) in gauges.iter() {
+            writeln!(out, "orphan_blocks{{node=\"{}\"}} {}", id, orphans).unwrap();
+        }
+        writeln!(out, "# HELP delayed_messages_in_flight Messages sleeping on a delayed link.").unwrap();
+        writeln!(out, "# TYPE delayed_messages_in_flight gauge").unwrap();
+        writeln!(out, "delayed_messages_in_flight {}", counters.in_flight.len()).unwrap();
+        counters.reorg_depth.render(&mut out, "reorg_depth", "Blocks abandoned by a reorg.");
+        counters.delivery_latency.render(&mut out, "delivery_latency_ms", "Time from sending a block to handing it to the receiver.");
+        out
+    }
+}
+
+fn counter_family(out: &mut String, name: &str, help: &str, label: &str, values: &BTreeMap<u8, u64>) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} counter", name).unwrap();
+    for (id, value) in values.iter() {
+        writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, id, value).unwrap();
+    }
+}
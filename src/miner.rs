@@ -2,98 +2,149 @@ use crossterm::{cursor, Result};
 use crossterm::terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType};
 use crossterm::style::{Color, SetForegroundColor, SetBackgroundColor, Print};
 
-use std::sync::{Arc, RwLock};
-use crate::block_tree::BlockTree;
-use std::sync::mpsc::{Sender, Receiver, channel};
-use crate::block::Block;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use crate::block_tree::{BlockTree, SharedTree};
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError};
+use crate::block::{self, Block, SealedBlock};
+use crate::state::State;
+use crate::transaction::Transaction;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::control::{self, Command};
 use std::io::stdout;
+use ring::signature::Ed25519KeyPair;
+
+// default timing, each overridable with `with_timing`
+pub const BLOCK_INT_MS: u64 = 2000;
+pub const MY_TURN_WAIT_MS: u64 = 10000;
 
 pub struct Miner {
     id: u8,
     n: u8,
+    // longest wait on the network, so a pause or stop is noticed
     sleep_ms: u64,
     block_int_ms: u64,
     my_turn_wait_ms: u64,
-    block_tree: Arc<RwLock<BlockTree>>,
-    to_network: Sender<Block>,
-    from_network: Receiver<Block>,
+    key: Ed25519KeyPair,
+    // only the miner touches its tree, and publishes it after every change
+    block_tree: BlockTree,
+    shared: SharedTree,
+    to_network: Sender<SealedBlock>,
+    from_network: Receiver<SealedBlock>,
+    // cleared to make the miner stop
+    running: Arc<AtomicBool>,
+    control: Receiver<Command>,
+    // time spent paused, which the miner's clock leaves out
+    paused_ms: u64,
 }
 
 impl Miner {
-    pub fn new(id: u8, n: u8, to_network: Sender<Block>, from_network: Receiver<Block>) -> (Miner, Arc<RwLock<BlockTree>>) {
-        let block_tree = BlockTree::default();
-        let block_tree = Arc::new(RwLock::new(block_tree));
-        let bt_clone = block_tree.clone();
+    pub fn new(id: u8, n: u8, block_tree: BlockTree, to_network: Sender<SealedBlock>, from_network: Receiver<SealedBlock>, running: Arc<AtomicBool>, control: Receiver<Command>) -> (Miner, SharedTree) {
+        let shared = SharedTree::new(&block_tree);
         let miner = Miner {
             id,
             n,
             sleep_ms: 100,
-            block_int_ms: 2000,
-            my_turn_wait_ms: 10000,
+            block_int_ms: BLOCK_INT_MS,
+            my_turn_wait_ms: MY_TURN_WAIT_MS,
+            key: block::miner_key(id),
             block_tree,
+            shared: shared.clone(),
             to_network,
-            from_network
+            from_network,
+            running,
+            control,
+            paused_ms: 0,
         };
-        (miner, bt_clone)
+        (miner, shared)
+    }
+
+    /// `block_int_ms` is how long the next miner in the ring waits after its parent, and every
+    /// further miner waits another `my_turn_wait_ms` before stepping in.
+    pub fn with_timing(mut self, block_int_ms: u64, my_turn_wait_ms: u64) -> Miner {
+        self.block_int_ms = block_int_ms;
+        self.my_turn_wait_ms = my_turn_wait_ms;
+        self
     }
 
-    pub fn start(mut self) {
-        std::thread::Builder::new().name(format!("Miner {}", self.id)).spawn(move || self.miner_loop()).unwrap();
+    pub fn start(mut self) -> JoinHandle<()> {
+        std::thread::Builder::new().name(format!("Miner {}", self.id)).spawn(move || self.miner_loop()).unwrap()
+    }
+
+    /// Wall clock in ms, minus the pauses.
+    fn now_ms(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 - self.paused_ms
     }
 
     fn miner_loop(&mut self) {
-        loop {
-            if let Ok(block) = self.from_network.try_recv() {
-                let mut store = self.block_tree.write().unwrap();
-                store.insert(block);
-            } else {
-                std::thread::sleep(Duration::from_millis(self.sleep_ms));
+        while self.running.load(Ordering::Relaxed) {
+            match control::wait_if_paused(&self.control) {
+                Some(paused) => self.paused_ms += paused.as_millis() as u64,
+                None => break,
             }
-            let parent = {
-                let store = self.block_tree.read().unwrap();
-                // if no genesis, should wait until genesis comes
-                if store.number_block.is_empty() {
-                    continue;
+            let now = self.now_ms();
+            // no genesis yet, or the tip's ancestors haven't arrived: nothing to build on
+            let due = self.block_tree.tip_state()
+                .and_then(|_| due_ms(self.id, self.n, self.block_int_ms, self.my_turn_wait_ms, &self.block_tree.tip));
+            if due.map_or(false, |due| due <= now) {
+                let state = self.block_tree.tip_state().unwrap().clone();
+                let block = new_block(self.id, self.n, &self.key, &self.block_tree.tip, state, now / 1000);
+                self.insert(block.clone());
+                if self.to_network.send(block).is_err() {
+                    // the network stopped
+                    break;
                 }
-                store.tip.clone()
-            };
-            if self.id == parent.miner {
                 continue;
             }
-            // my turn to mine
-            if self.id == (parent.miner + 1) % self.n {
-                let expect_timestamp = UNIX_EPOCH + Duration::from_secs(parent.timestamp) + Duration::from_millis(self.block_int_ms);
-                if let Ok(duration) = expect_timestamp.duration_since(SystemTime::now()) {
-                    std::thread::sleep(duration);
-                }
-                let block = Block::new(self.id, &parent);
-                {
-                    let mut store = self.block_tree.write().unwrap();
-                    store.insert(block.clone());
-                }
-                self.to_network.send(block).unwrap();
-            } else {
-                // should skip the one just next to genesis
-                if parent.timestamp == 10101 {
-                    continue;
-                }
-                // not my turn, wait
-                let h = hop(self.n, parent.miner, self.id);
-                let gap = h as u64 * self.my_turn_wait_ms;
-                let expect_timestamp = UNIX_EPOCH + Duration::from_secs(parent.timestamp) + Duration::from_millis(gap);
-                if expect_timestamp > SystemTime::now() {
-                    continue;
-                }
-                let block = Block::new(self.id, &parent);
-                {
-                    let mut store = self.block_tree.write().unwrap();
-                    store.insert(block.clone());
-                }
-                self.to_network.send(block).unwrap();
+            // wait for the network, but not past the time my next block is due
+            let wait = due.map_or(self.sleep_ms, |due| (due - now).min(self.sleep_ms));
+            match self.from_network.recv_timeout(Duration::from_millis(wait)) {
+                Ok(block) => self.insert(block),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
             }
         }
     }
+
+    fn insert(&mut self, block: SealedBlock) {
+        self.block_tree.insert(block);
+        self.shared.publish(&self.block_tree);
+    }
+}
+
+/// When (in ms since the epoch) miner `id` builds on `parent`, if ever: the next miner in the
+/// ring `block_int_ms` after the parent, every further one another `my_turn_wait_ms` later.
+pub fn due_ms(id: u8, n: u8, block_int_ms: u64, my_turn_wait_ms: u64, parent: &Block) -> Option<u64> {
+    if id == parent.miner {
+        return None;
+    }
+    if id == (parent.miner + 1) % n {
+        return Some(parent.timestamp * 1000 + block_int_ms);
+    }
+    // should skip the one just next to genesis
+    if parent.number == 0 {
+        return None;
+    }
+    let h = hop(n, parent.miner, id);
+    Some(parent.timestamp * 1000 + h as u64 * my_turn_wait_ms)
+}
+
+/// Pay one coin forward to the next miner in the ring, if we can afford it.
+fn transactions(id: u8, n: u8, state: &State) -> Vec<Transaction> {
+    if state.balance(id) == 0 {
+        return vec![];
+    }
+    vec![Transaction::new(id, (id + 1) % n, 1, state.nonce(id))]
+}
+
+/// Miner `id`'s block on top of `parent`, whose post-state is `state`, signed and sealed.
+pub fn new_block(id: u8, n: u8, key: &Ed25519KeyPair, parent: &SealedBlock, mut state: State, timestamp: u64) -> SealedBlock {
+    let transactions = transactions(id, n, &state);
+    state.apply(id, &transactions);
+    let mut block = Block::new(id, parent, transactions, state.root(), timestamp);
+    block.sign(key);
+    SealedBlock::new(block).expect("Mined an invalid block")
 }
 
 fn hop(n: u8, pre: u8, cur: u8) -> u8 {
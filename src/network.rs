@@ -2,23 +2,48 @@ use crossterm::{cursor, Result};
 use crossterm::terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType};
 use crossterm::style::{Color, SetForegroundColor, SetBackgroundColor, Print};
 
-use std::sync::mpsc::{Receiver, channel, Sender};
-use crate::block::Block;
-use crate::block_tree::BlockTree;
-use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
+use std::sync::mpsc::{Receiver, Sender, RecvTimeoutError};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use crate::block::{Header, SealedBlock};
+use crate::events::{Event, EventBus};
+use crate::control::{self, Command};
+use std::collections::{BTreeMap, HashMap};
 use std::io::stdout;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::hash::Hash;
 
 pub struct Network {
     pub n: u8,
-    pub from_miners: Receiver<Block>,
-    pub senders: HashMap<u8, Sender<Block>>,
+    pub from_miners: Receiver<SealedBlock>,
+    pub senders: HashMap<u8, Sender<SealedBlock>>,
+    // light clients only get headers and never send anything back
+    pub light_senders: HashMap<u8, Sender<Header>>,
     pub artificial_delay: HashMap<(u8,u8), u64>,
-    pub log: Arc<RwLock<String>>,
+    pub events: EventBus,
+    // cleared to make the network stop
+    pub running: Arc<AtomicBool>,
+    pub control: Receiver<Command>,
 }
 
+enum Message {
+    Block(SealedBlock),
+    Header(Header),
+}
+
+/// A message on its way, held by the network until its artificial delay is over.
+struct Delayed {
+    from: u8,
+    to: u8,
+    hash: String,
+    sent: Instant,
+    message: Message,
+}
+
+// delayed messages by when they are due, then by order sent
+type Pending = BTreeMap<(Instant, u64), Delayed>;
+
 
 impl Network {
 
@@ -32,49 +57,105 @@ impl Network {
     }
 
     pub fn genesis(&self)  -> Result<()> {
-        let block = Block::genesis();
+        let block = SealedBlock::genesis();
+        // a node that stopped already doesn't need it
         for id in 0..self.n {
             if let Some(sender) = self.senders.get(&id) {
-                sender.send(block.clone()).unwrap();
+                let _ = sender.send(block.clone());
             }
         }
+        for sender in self.light_senders.values() {
+            let _ = sender.send(block.header());
+        }
         Ok(())
     }
 
+    /// Send `message` from `from` to `to` now, or put it in `pending` if the link has an artificial delay.
+    fn deliver(&self, from: u8, to: u8, hash: &str, message: Message, pending: &mut Pending, seq: &mut u64) {
+        let delay = self.artificial_delay.get(&(from, to)).copied();
+        self.events.publish(Event::BlockSent {
+            from,
+            to,
+            delay: delay.unwrap_or_default(),
+            hash: hash.to_string(),
+        });
+        let sent = Instant::now();
+        let delayed = Delayed { from, to, hash: hash.to_string(), sent, message };
+        match delay {
+            Some(d) => {
+                pending.insert((sent + Duration::from_millis(d), *seq), delayed);
+                *seq += 1;
+            }
+            None => self.hand_over(delayed),
+        }
+    }
+
+    fn hand_over(&self, delayed: Delayed) {
+        let Delayed { from, to, hash, sent, message } = delayed;
+        let received = match message {
+            Message::Block(block) => self.senders.get(&to).map_or(false, |sender| sender.send(block).is_ok()),
+            Message::Header(header) => self.light_senders.get(&to).map_or(false, |sender| sender.send(header).is_ok()),
+        };
+        // the receiver is gone if the simulation is stopping
+        if received {
+            self.events.publish(Event::BlockDelivered {
+                from,
+                to,
+                delay: sent.elapsed().as_millis() as u64,
+                hash,
+            });
+        }
+    }
+
     fn main_loop(&self)  -> Result<()> {
         self.genesis()?;
-        loop {
-            let block = self.from_miners.recv().unwrap();
-            let mut log = String::new();
-            log += format!("Mined block {} ", &hex::encode(block.digest())[..4]).as_ref();
+        let mut pending = Pending::new();
+        let mut seq = 0;
+        while self.running.load(Ordering::Relaxed) {
+            // messages on their way wait too, and aren't late because of it
+            match control::wait_if_paused(&self.control) {
+                Some(paused) if paused > Duration::default() => {
+                    pending = pending.into_iter().map(|((due, seq), mut delayed)| {
+                        delayed.sent += paused;
+                        ((due + paused, seq), delayed)
+                    }).collect();
+                }
+                Some(_) => {}
+                None => break,
+            }
+            let now = Instant::now();
+            while let Some(key) = pending.keys().next().copied().filter(|(due, _)| *due <= now) {
+                let delayed = pending.remove(&key).unwrap();
+                self.hand_over(delayed);
+            }
+            let timeout = pending.keys().next()
+                .map_or(Duration::from_millis(100), |(due, _)| due.saturating_duration_since(now))
+                .min(Duration::from_millis(100));
+            let block = match self.from_miners.recv_timeout(timeout) {
+                Ok(block) => block,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            let hash = hex::encode(block.digest());
+            self.events.publish(Event::BlockMined {
+                miner: block.miner,
+                hash: hash.clone(),
+                number: block.number,
+            });
             for id in 0..self.n {
                 if id == block.miner {
                     continue;
                 }
-                if let Some(sender) = self.senders.get(&id) {
-                    if let Some(d) = self.artificial_delay.get(&(block.miner, id)) {
-                        let d = *d;
-                        let sender = sender.clone();
-                        let block = block.clone();
-                        std::thread::Builder::new().name(format!("network artificial delay")).spawn(move || {
-                            //let mut stdout = stdout();
-                            //execute!(stdout, cursor::MoveTo(0,1), Clear(ClearType::CurrentLine), SetForegroundColor(Color::White), Print(format!("Delay from {} to {} is {}ms",block.miner,id,d))).unwrap();
-                            std::thread::sleep(Duration::from_millis(d));
-                            sender.send(block).unwrap();
-                        }).unwrap();
-                        log += format!("delay to {}: {} ms;\t", id, d).as_ref();
-                    } else {
-                        sender.send(block.clone()).unwrap();
-                    };
-                }
+                self.deliver(block.miner, id, &hash, Message::Block(block.clone()), &mut pending, &mut seq);
+            }
+            for id in self.light_senders.keys() {
+                self.deliver(block.miner, *id, &hash, Message::Header(block.header()), &mut pending, &mut seq);
             }
-            let mut log_ = self.log.write().unwrap();
-            log_.clear();
-            log_.push_str(log.as_ref());
         }
+        Ok(())
     }
 
-    pub fn start(self) {
-        std::thread::Builder::new().name(format!("network")).spawn(move || self.main_loop()).unwrap();
+    pub fn start(self) -> JoinHandle<()> {
+        std::thread::Builder::new().name(format!("network")).spawn(move || self.main_loop().expect("Network error")).unwrap()
     }
-}
\ No newline at end of file
+}
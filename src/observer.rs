@@ -0,0 +1,49 @@
+use crate::block::Block;
+use crate::events::{Event, EventBus, Record};
+
+/// Callbacks for instrumenting a simulation, each with the fields of its event. They are called by
+/// the thread the event happens in (miner, network, engine or light client), one thread at a time
+/// per observer, so a slow one holds up those threads. Unimplemented ones do nothing.
+#[allow(unused_variables)]
+pub trait SimulationObserver: Send {
+    /// Every event, before the callback for its kind.
+    fn on_event(&mut self, record: &Record) {}
+
+    fn on_block_mined(&mut self, miner: u8, hash: &str, number: u64) {}
+
+    // `delay` is the artificial delay of the link
+    fn on_message_sent(&mut self, from: u8, to: u8, delay: u64, hash: &str) {}
+
+    // `delay` is how long it took in ms
+    fn on_message_delivered(&mut self, from: u8, to: u8, delay: u64, hash: &str) {}
+
+    // `block` is None for light clients, they only get the header
+    fn on_block_inserted(&mut self, node: u8, miner: u8, hash: &str, number: u64, block: Option<&Block>) {}
+
+    fn on_tip_changed(&mut self, node: u8, hash: &str, number: u64) {}
+
+    fn on_reorg(&mut self, node: u8, old_tip: &str, new_tip: &str, depth: u64, abandoned: &[String]) {}
+
+    fn on_rejected(&mut self, node: u8, hash: &str, reason: &str) {}
+}
+
+fn dispatch(observer: &mut dyn SimulationObserver, record: &Record) {
+    observer.on_event(record);
+    match &record.event {
+        Event::BlockMined { miner, hash, number } => observer.on_block_mined(*miner, hash, *number),
+        Event::BlockSent { from, to, delay, hash } => observer.on_message_sent(*from, *to, *delay, hash),
+        Event::BlockDelivered { from, to, delay, hash } => observer.on_message_delivered(*from, *to, *delay, hash),
+        Event::BlockInserted { node, miner, hash, number, block } => observer.on_block_inserted(*node, *miner, hash, *number, block.as_deref()),
+        Event::TipChanged { node, hash, number } => observer.on_tip_changed(*node, hash, *number),
+        Event::Reorg { node, old_tip, new_tip, depth, abandoned } => observer.on_reorg(*node, old_tip, new_tip, *depth, abandoned),
+        Event::Rejected { node, hash, reason } => observer.on_rejected(*node, hash, reason),
+    }
+}
+
+impl EventBus {
+    /// Register `observer` for every event published from now on. Any number can be registered;
+    /// they are called in the order they were added.
+    pub fn observe(&self, mut observer: impl SimulationObserver + 'static) {
+        self.hook(move |record| dispatch(&mut observer, record));
+    }
+}
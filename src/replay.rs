@@ -0,0 +1,24 @@
+use std::collections::BTreeMap;
+use crate::block_tree::BlockTree;
+use crate::events::{Event, EventBus, Record};
+
+/// Rebuild every full node's `BlockTree` from a recorded event log, inserting blocks in the order
+/// the nodes did. With `until_ms`, only records up to that many ms after the first one are used.
+pub fn replay(records: &[Record], until_ms: Option<u64>) -> BTreeMap<u8, BlockTree> {
+    let mut trees: BTreeMap<u8, BlockTree> = BTreeMap::new();
+    let start = match records.first() {
+        Some(record) => record.time_ms,
+        None => return trees,
+    };
+    for record in records {
+        if until_ms.map_or(false, |until| record.time_ms > start + until) {
+            break;
+        }
+        if let Event::BlockInserted { node, block: Some(block), .. } = &record.event {
+            trees.entry(*node)
+                .or_insert_with(|| BlockTree::new(*node, EventBus::default()))
+                .insert(block.clone());
+        }
+    }
+    trees
+}
@@ -0,0 +1,195 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::BlockTree;
+use crate::analysis::{self, Analysis};
+use crate::events::{Event, EventBus, Record};
+use crate::simulation::{Config, Simulation};
+
+#[derive(Serialize, Debug, Clone)]
+pub struct NodeReport {
+    pub id: u8,
+    pub tip: String,
+    pub tip_height: u64,
+    // levels with more than one block
+    pub forks: usize,
+    pub blocks: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MinerReport {
+    pub id: u8,
+    pub mined: usize,
+    // mined blocks that ended up on the canonical chain
+    pub canonical: usize,
+    pub stale_rate: f64,
+}
+
+/// Summary of a finished run.
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub duration_secs: f64,
+    pub delay: Vec<(u8, u8, u64)>,
+    pub nodes: Vec<NodeReport>,
+    pub miners: Vec<MinerReport>,
+    pub canonical_height: u64,
+    pub average_block_interval_secs: Option<f64>,
+    // mean time from a canonical block being mined until every node had it
+    pub time_to_agreement_secs: Option<f64>,
+    pub analysis: Analysis,
+}
+
+impl Report {
+    pub fn new(trees: &[&BlockTree], records: &[Record], delay: Vec<(u8, u8, u64)>, duration_secs: f64) -> Report {
+        let canonical = analysis::canonical_chain(trees);
+        let canonical_digests: HashSet<BlockHash> = canonical.iter().map(SealedBlock::digest).collect();
+
+        let mut all_blocks: HashMap<BlockHash, u8> = HashMap::new();
+        let nodes = trees.iter().map(|tree| {
+            for block in tree.number_block.values().flat_map(|blocks| blocks.iter()) {
+                if block.number > 0 {
+                    all_blocks.insert(block.digest(), block.miner);
+                }
+            }
+            NodeReport {
+                id: tree.id,
+                tip: hex::encode(tree.tip.digest()),
+                tip_height: tree.tip.number,
+                forks: tree.number_block.values().filter(|blocks| blocks.len() > 1).count(),
+                blocks: tree.numbers.len(),
+            }
+        }).collect();
+
+        let miners = trees.iter().map(|tree| {
+            let mined = all_blocks.values().filter(|miner| **miner == tree.id).count();
+            let canonical = all_blocks.iter()
+                .filter(|(digest, miner)| **miner == tree.id && canonical_digests.contains(*digest))
+                .count();
+            MinerReport {
+                id: tree.id,
+                mined,
+                canonical,
+                stale_rate: if mined > 0 { (mined - canonical) as f64 / mined as f64 } else { 0.0 },
+            }
+        }).collect();
+
+        // the chain is tip first; genesis has a made-up timestamp
+        let mined: Vec<&SealedBlock> = canonical.iter().filter(|block| block.number > 0).collect();
+        let average_block_interval_secs = match (mined.last(), mined.first()) {
+            (Some(first), Some(last)) if mined.len() > 1 => {
+                Some((last.timestamp - first.timestamp) as f64 / (mined.len() - 1) as f64)
+            }
+            _ => None,
+        };
+
+        Report {
+            duration_secs,
+            delay,
+            nodes,
+            miners,
+            canonical_height: canonical.first().map_or(0, |tip| tip.number),
+            average_block_interval_secs,
+            time_to_agreement_secs: time_to_agreement(records, &canonical_digests, trees.len()),
+            analysis: Analysis::new(trees, records, duration_secs),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("ran {:.1} s, canonical height {}", self.duration_secs, self.canonical_height);
+        for node in self.nodes.iter() {
+            println!("node{}: tip {} at level {}, {} blocks, {} fork levels", node.id, &node.tip[..4], node.tip_height, node.blocks, node.forks);
+        }
+        for miner in self.miners.iter() {
+            println!("miner{}: mined {}, canonical {}, stale rate {:.2}", miner.id, miner.mined, miner.canonical, miner.stale_rate);
+        }
+        match self.average_block_interval_secs {
+            Some(interval) => println!("average block interval {:.2} s", interval),
+            None => println!("average block interval n/a"),
+        }
+        match self.time_to_agreement_secs {
+            Some(time) => println!("time to agreement {:.2} s", time),
+            None => println!("time to agreement n/a"),
+        }
+        let analysis = &self.analysis;
+        println!("chain growth {:.3} levels/s, stale rate {:.2}, longest fork {}", analysis.chain_growth, analysis.stale_rate, analysis.longest_fork);
+        for quality in analysis.chain_quality.iter() {
+            println!("miner{}: {:.2} of the chain, {:.2} expected", quality.id, quality.share, quality.expected);
+        }
+        for pair in analysis.time_to_consistency.iter() {
+            println!("node{} ~ node{}: {:.2} s mean, {:.2} s max apart", pair.a, pair.b, pair.mean_secs, pair.max_secs);
+        }
+        println!("delay {:?}", self.delay);
+    }
+}
+
+fn time_to_agreement(records: &[Record], canonical: &HashSet<BlockHash>, nodes: usize) -> Option<f64> {
+    let mut mined_at: HashMap<&str, u64> = HashMap::new();
+    let mut inserted: HashMap<&str, (HashSet<u8>, u64)> = HashMap::new();
+    for record in records {
+        match &record.event {
+            Event::BlockMined { hash, .. } => {
+                mined_at.insert(hash, record.time_ms);
+            }
+            Event::BlockInserted { node, hash, block: Some(_), .. } => {
+                let (seen, last) = inserted.entry(hash).or_default();
+                seen.insert(*node);
+                *last = record.time_ms;
+            }
+            _ => {}
+        }
+    }
+    let times: Vec<u64> = canonical.iter().filter_map(|digest| {
+        let hash = hex::encode(digest);
+        let mined = mined_at.get(hash.as_str())?;
+        let (seen, last) = inserted.get(hash.as_str())?;
+        if seen.len() < nodes {
+            return None;
+        }
+        Some(last.saturating_sub(*mined))
+    }).collect();
+    if times.is_empty() {
+        return None;
+    }
+    Some(times.iter().sum::<u64>() as f64 / times.len() as f64 / 1000.0)
+}
+
+/// Run until `duration` seconds passed, some node reached `blocks` levels or `interrupt` fires,
+/// stop every thread and summarize the run.
+pub fn run_batch(config: Config, events: EventBus, duration: Option<f64>, blocks: Option<u64>, interrupt: Option<&Receiver<()>>) -> Result<Report, Box<dyn Error>> {
+    if duration.is_none() && blocks.is_none() {
+        return Err("a batch run needs --duration or --blocks".into());
+    }
+    let records = events.subscribe();
+    let mut pretty_delay: Vec<(u8,u8,u64)> = config.delay.iter().map(|((i,j),k)| (*i,*j,*k)).collect();
+    pretty_delay.sort();
+    let mut simulation = Simulation::start(config, events)?;
+    let start_time = Instant::now();
+    loop {
+        match interrupt {
+            Some(interrupt) => match interrupt.recv_timeout(Duration::from_millis(100)) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => break,
+            },
+            None => std::thread::sleep(Duration::from_millis(100)),
+        }
+        let elapsed = start_time.elapsed().as_secs_f64();
+        if duration.map_or(false, |duration| elapsed >= duration) {
+            break;
+        }
+        let height = simulation.stores.values().map(|store| store.load().tip.number).max().unwrap_or(0);
+        if blocks.map_or(false, |blocks| height >= blocks) {
+            break;
+        }
+    }
+    simulation.stop()?;
+    let elapsed = start_time.elapsed().as_secs_f64();
+    let records: Vec<_> = records.try_iter().collect();
+    let mut ids: Vec<u8> = simulation.stores.keys().copied().collect();
+    ids.sort();
+    let reads: Vec<_> = ids.iter().map(|id| simulation.stores[id].load()).collect();
+    let trees: Vec<&BlockTree> = reads.iter().map(|read| &**read).collect();
+    Ok(Report::new(&trees, &records, pretty_delay, elapsed))
+}
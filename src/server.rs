@@ -4,16 +4,41 @@ use tiny_http::Header;
 use tiny_http::Response;
 use tiny_http::Server as HTTPServer;
 use url::Url;
-use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, RwLock};
-use crate::block_tree::BlockTree;
-use std::thread;
-use crate::block::Block;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use crate::block_tree::{BlockTree, TreeSnapshot};
+use std::thread::{self, JoinHandle};
+use crate::block;
 use crate::network::Network;
+use crate::api;
+use crate::svg;
+use crate::events::EventBus;
+use crate::control::Control;
+use crate::simulation::{self, Config};
+use crate::analysis::Analysis;
+use crate::diff::Diff;
+use std::io::Write;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use crate::export::{Format, SimulationExport, TreeExport};
 
 pub struct Server {
-    stores: HashMap<u8, Arc<RwLock<BlockTree>>>,
-    handle: HTTPServer,
+    control: Arc<Control>,
+    events: EventBus,
+    handle: Arc<HTTPServer>,
+}
+
+/// A started server; `stop` closes the socket and waits for the requests in flight.
+pub struct ServerHandle {
+    http: Arc<HTTPServer>,
+    thread: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    pub fn stop(self) {
+        self.http.unblock();
+        self.thread.join().expect("Server thread panicked");
+    }
 }
 
 static ERROR_404: &str = "<!DOCTYPE html>
@@ -23,6 +48,112 @@ static ERROR_404: &str = "<!DOCTYPE html>
 	</body>
 </html>";
 
+/// Number of levels the dashboard shows when no range is given.
+const DASHBOARD_LEVELS: u64 = 50;
+
+/// The `from` and `to` levels asked for, by default the last `DASHBOARD_LEVELS` up to `last_number`.
+fn window(params: &HashMap<String, String>, last_number: u64) -> Result<(u64, u64), String> {
+    let parse = |name: &str| -> Result<Option<u64>, String> {
+        match params.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("{} should be a level", name)),
+            None => Ok(None),
+        }
+    };
+    let to = parse("to")?.unwrap_or(last_number).min(last_number);
+    let from = parse("from")?.unwrap_or_else(|| to.saturating_sub(DASHBOARD_LEVELS - 1));
+    if from > to {
+        return Err(format!("from {} is above to {}", from, to));
+    }
+    Ok((from, to))
+}
+
+/// The `at` parameter in seconds since the start of the run, as ms.
+fn at_param(params: &HashMap<String, String>) -> Result<Option<u64>, String> {
+    match params.get("at") {
+        Some(at) => match at.parse::<f64>() {
+            Ok(seconds) if seconds >= 0.0 => Ok(Some((seconds * 1000.0) as u64)),
+            _ => Err(format!("at should be seconds since the start, not {}", at)),
+        },
+        None => Ok(None),
+    }
+}
+
+/// The config for `/control/reset`: the current one with whatever the query changes. A reset
+/// starts from genesis in memory, so the data dir and import are dropped.
+fn reset_config(params: &HashMap<String, String>, mut config: Config) -> Result<Config, String> {
+    let parse = |name: &str| -> Result<Option<u64>, String> {
+        match params.get(name) {
+            Some(value) => value.parse().map(Some).map_err(|_| format!("{} should be a number", name)),
+            None => Ok(None),
+        }
+    };
+    if let Some(nodes) = parse("nodes")? {
+        if !(2..=200).contains(&nodes) {
+            return Err("nodes should be between 2 and 200".to_string());
+        }
+        config.nodes = Some(nodes as u8);
+    }
+    if let Some(ms) = parse("block_int_ms")? {
+        config.block_int_ms = Some(ms);
+    }
+    if let Some(ms) = parse("my_turn_wait_ms")? {
+        config.my_turn_wait_ms = Some(ms);
+    }
+    if let Some(ms) = parse("ring_delay")? {
+        config.delay = simulation::ring_delay(config.nodes(), ms);
+    }
+    if let Some(deterministic) = params.get("deterministic") {
+        config.deterministic = deterministic == "1" || deterministic == "true";
+    }
+    config.data_dir = None;
+    config.import = None;
+    Ok(config)
+}
+
+/// Appends blocks to the level table as `BlockInserted` events come in, and keeps the other
+/// events in a log. `COLUMNS` is replaced with the node ids in column order.
+static LIVE_SCRIPT: &str = r#"
+var columns = COLUMNS;
+var log = document.getElementById("log");
+var source = new EventSource("events");
+function cell(node, number) {
+    var table = document.getElementById("levels");
+    var last = table.rows[table.rows.length - 1];
+    var next = last.id ? parseInt(last.id.substr("level".length)) + 1 : 0;
+    for (var level = next; level <= number; level++) {
+        var row = table.insertRow(-1);
+        row.id = "level" + level;
+        row.insertCell(-1).textContent = level;
+        columns.forEach(function (id) {
+            row.insertCell(-1).id = "cell-" + id + "-" + level;
+        });
+    }
+    return document.getElementById("cell-" + node + "-" + number);
+}
+source.addEventListener("BlockInserted", function (e) {
+    var ev = JSON.parse(e.data);
+    var span = document.createElement("span");
+    span.className = "node" + ev.miner;
+    span.textContent = ev.hash.substr(0, 4) + " ";
+    // blocks below the window have no cell
+    var target = cell(ev.node, ev.number);
+    if (target) {
+        target.appendChild(span);
+    }
+});
+["BlockMined", "TipChanged", "Reorg"].forEach(function (name) {
+    source.addEventListener(name, function (e) {
+        var lines = log.textContent.split("\n").slice(0, 19);
+        log.textContent = [name + " " + e.data].concat(lines).join("\n");
+    });
+});
+"#;
+
+/// The script behind `dashboard?live=1`, updating the table for the given columns.
+pub fn live_script(columns: &[u8]) -> String {
+    LIVE_SCRIPT.replace("COLUMNS", &serde_json::to_string(columns).expect("Json serialize error"))
+}
+
 /// This macro serves the string as html
 macro_rules! serve_string {
     ( $req:expr, $message:expr ) => {{
@@ -42,6 +173,26 @@ macro_rules! serve_json {
         $req.respond(resp).unwrap();
     }};
 }
+/// This macro serves the svg image
+macro_rules! serve_svg {
+    ( $req:expr, $message:expr ) => {{
+        let content_type = "Content-Type: image/svg+xml".parse::<Header>().unwrap();
+        let resp = Response::from_string($message)
+            .with_header(content_type);
+        $req.respond(resp).unwrap();
+    }};
+}
+
+/// This macro serves an error message as json with the given status code
+macro_rules! serve_json_error {
+    ( $req:expr, $status:expr, $message:expr ) => {{
+        let content_type = "Content-Type: application/json".parse::<Header>().unwrap();
+        let resp = Response::from_string(serde_json::json!({ "error": $message }).to_string())
+            .with_header(content_type)
+            .with_status_code($status);
+        $req.respond(resp).unwrap();
+    }};
+}
 // /// This macro serves the static file at the location `path` and attaches the content type `type`.
 // macro_rules! serve_static_file {
 //     ( $req:expr, $path:expr, $type:expr ) => {{
@@ -79,20 +230,28 @@ macro_rules! serve_json {
 impl Server {
     pub fn start(
         addr: std::net::SocketAddr,
-        stores: HashMap<u8, Arc<RwLock<BlockTree>>>,
-        delay: &HashMap<(u8,u8), u64>,
-    ) {
-        let handle = HTTPServer::http(&addr).unwrap();
+        control: Arc<Control>,
+        events: EventBus,
+    ) -> ServerHandle {
+        let handle = Arc::new(HTTPServer::http(&addr).unwrap());
         let server = Self {
-            stores,
-            handle,
+            control,
+            events,
+            handle: handle.clone(),
         };
-        let delay = delay.clone();
-        thread::spawn(move || {
+        let thread = thread::spawn(move || {
+            let mut requests: Vec<JoinHandle<()>> = vec![];
             for req in server.handle.incoming_requests() {
-                let stores = server.stores.clone();
-                let delay = delay.clone();
-                thread::spawn(move || {
+                requests.retain(|request| !request.is_finished());
+                // the simulation may have been reset since the last request
+                let control = server.control.clone();
+                let stores = control.stores();
+                let light_stores = control.light_stores();
+                let delay = control.config().delay;
+                let events = server.events.clone();
+                let metrics = control.metrics.clone();
+                let history = control.history.clone();
+                requests.push(thread::spawn(move || {
                     // a valid url requires a base
                     let base_url = Url::parse(&format!("http://{}/", &addr)).unwrap();
                     let url = match base_url.join(req.url()) {
@@ -115,21 +274,61 @@ impl Server {
                             } else {
                                 false
                             };
-                            let last_number = stores.values().map(|store| {
-                                let read = store.read().unwrap();
-                                read.tip.number
-                            }).max().expect("Error when find max tip");
-                            let mut stores_for_output: HashMap<(u8,u64),HashSet<Block>> = Default::default();
-                            for id in 0..stores.len() as u8 {
-                                let read = stores.get(&id).unwrap();
-                                let read = read.read().unwrap();
-                                let tip_number = read.tip.number;
-                                for level in 0..=tip_number {
-                                    if let Some(blocks ) = read.number_block.get(&level) {
-                                        stores_for_output.insert((id, level), blocks.clone());
-                                    }
+                            let at = match at_param(&params) {
+                                Ok(at) => at,
+                                Err(message) => {
+                                    serve_json_error!(req, 400, message);
+                                    return;
                                 }
-                            }
+                            };
+                            // the past doesn't change, so only the present refreshes
+                            let refresh = refresh && at.is_none();
+                            // live pages keep themselves up to date from /events instead of reloading
+                            let live = params.contains_key("live") && at.is_none();
+                            let replayed = at.map(|at| history.replay(at));
+                            let duration = history.duration_ms() / 1000;
+                            let last_number = match &replayed {
+                                Some(trees) => trees.values().map(|tree| tree.tip.number).max().unwrap_or_default(),
+                                None => stores.values().map(|store| {
+                                    let read = store.load();
+                                    read.tip.number
+                                }).max().expect("Error when find max tip"),
+                            };
+                            let (from, to) = match window(&params, last_number) {
+                                Ok(window) => window,
+                                Err(message) => {
+                                    serve_json_error!(req, 400, message);
+                                    return;
+                                }
+                            };
+                            // copy just the window of each snapshot and render from the copies
+                            let snapshots: HashMap<u8, TreeSnapshot> = (0..stores.len() as u8).map(|id| {
+                                let snapshot = match &replayed {
+                                    Some(trees) => trees.get(&id).map_or_else(|| BlockTree::default().snapshot(from, to), |tree| tree.snapshot(from, to)),
+                                    None => stores.get(&id).unwrap().load().snapshot(from, to),
+                                };
+                                (id, snapshot)
+                            }).collect();
+                            // light clients have no bodies in the history, so they only show up live
+                            let mut light_ids: Vec<u8> = match at {
+                                Some(_) => vec![],
+                                None => light_stores.keys().copied().collect(),
+                            };
+                            light_ids.sort_unstable();
+                            let light_snapshots: HashMap<u8, BTreeMap<u64, HashSet<block::Header>>> = light_ids.iter().map(|id| {
+                                let read = light_stores.get(id).unwrap().read().unwrap();
+                                (*id, read.snapshot(from, to))
+                            }).collect();
+                            let older = from.saturating_sub(DASHBOARD_LEVELS);
+                            let mode = match at {
+                                Some(at) => format!("&at={}", at as f64 / 1000.0),
+                                None if live => "&live=1".to_string(),
+                                None if refresh => "&refresh=1".to_string(),
+                                None => String::new(),
+                            };
+                            let at_seconds = at.map_or(duration, |at| at / 1000);
+                            let columns: Vec<u8> = (0..stores.len() as u8).chain(light_ids.iter().copied()).collect();
+                            let script = live_script(&columns);
                             let page = format!("{}", html! {
                                 : doctype::HTML;
                                 html {
@@ -139,29 +338,136 @@ impl Server {
                                             : r".node0{color:green}.node1{color:blue}.node2{color:red}.node3{color:cyan}.node4{color:yellow}.node5{color:magenta}";
                                             : r"table, th, td { border: 1px solid black; }"
                                         }
-                                        @ if refresh {
+                                        @ if refresh && !live {
                                             meta(http-equiv="refresh", content="1");
                                         }
                                     }
                                     body {
                                         // attributes
-                                        table {
+                                        p {
+                                            : "Time ";
+                                            input(type="range", id="at", min="0", max=format_args!("{}", duration), value=format_args!("{}", at_seconds),
+                                                  oninput="document.getElementById('at-label').textContent = this.value + ' s'",
+                                                  onchange="location.href = 'dashboard?at=' + this.value");
+                                            span(id="at-label") : format_args!("{} s", at_seconds);
+                                            @ if at.is_some() {
+                                                : " ";
+                                                a(href="dashboard") : "back to now";
+                                            }
+                                        }
+                                        p {
+                                            @ if from > 0 {
+                                                a(href=format_args!("dashboard?from={}&to={}{}", older, from - 1, mode)) : "older ";
+                                            }
+                                            @ if to < last_number {
+                                                a(href=format_args!("dashboard?from={}&to={}{}", to + 1, (to + DASHBOARD_LEVELS).min(last_number), mode)) : "newer ";
+                                                a(href=format_args!("dashboard?{}", mode.trim_start_matches('&'))) : "latest";
+                                            }
+                                            : format_args!(" levels {} to {} of {}", from, to, last_number);
+                                        }
+                                        table(id="levels") {
                                             tr {
                                                 th : "Level";
-                                                @ for id in 0..stores.len() {
-                                                    th(class=format_args!("node{}", id)) : format_args!("node{}", id);
+                                                @ for id in 0..stores.len() as u8 {
+                                                    th(class=format_args!("node{}", id), title=format_args!("tip at level {}", snapshots[&id].tip.number)) : format_args!("node{}", id);
+                                                }
+                                                @ for id in light_ids.iter() {
+                                                    th : format_args!("light{}", id);
                                                 }
                                             }
-                                            @ for level in 0..=last_number {
-                                                tr {
+                                            @ for level in from..=to {
+                                                tr(id=format_args!("level{}", level)) {
                                                     td : format_args!("{}", level);
                                                     @ for id in 0..stores.len() as u8 {
-                                                        @ if let Some(blocks) = stores_for_output.get(&(id, level)) {
-                                                            td {
+                                                        @ if let Some(blocks) = snapshots[&id].levels.get(&level) {
+                                                            td(id=format_args!("cell-{}-{}", id, level)) {
                                                                 @ for block in blocks.iter() {
                                                                     span(class=format_args!("node{}", block.miner), title=format_args!("{}", block)) : format_args!("{} ", &hex::encode(block.digest())[..4]);
                                                                 }
                                                             }
+                                                        } else {
+                                                            td(id=format_args!("cell-{}-{}", id, level)) ;
+                                                        }
+                                                    }
+                                                    @ for id in light_ids.iter() {
+                                                        @ if let Some(headers) = light_snapshots[id].get(&level) {
+                                                            td(id=format_args!("cell-{}-{}", id, level)) {
+                                                                @ for header in headers.iter() {
+                                                                    span(class=format_args!("node{}", header.miner), title=format_args!("{}", header)) : format_args!("{} ", &hex::encode(header.digest())[..4]);
+                                                                }
+                                                            }
+                                                        } else {
+                                                            td(id=format_args!("cell-{}-{}", id, level)) ;
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        @ if live {
+                                            pre(id="log");
+                                            script : Raw(&script);
+                                        }
+                                        h3 : "Block trees";
+                                        p {
+                                            : "Per node: ";
+                                            @ for id in 0..stores.len() {
+                                                a(href=format_args!("tree?node={}", id), class=format_args!("node{}", id)) : format_args!("node{} ", id);
+                                            }
+                                        }
+                                        img(src=format_args!("tree?from={}&to={}{}", from, to, mode), alt="merged block tree");
+                                        h3 : "Reorgs";
+                                        p {
+                                            @ for id in 0..stores.len() as u8 {
+                                                span(class=format_args!("node{}", id)) : format_args!("node{}: {} ", id, snapshots[&id].reorgs.len());
+                                            }
+                                        }
+                                        table {
+                                            tr {
+                                                th : "Node";
+                                                th : "Level";
+                                                th : "Depth";
+                                                th : "Old tip";
+                                                th : "New tip";
+                                                th : "Abandoned";
+                                            }
+                                            // the latest few of each node
+                                            @ for id in 0..stores.len() as u8 {
+                                                @ for reorg in snapshots[&id].reorgs.iter().rev().take(10) {
+                                                    tr {
+                                                        td(class=format_args!("node{}", id)) : format_args!("node{}", id);
+                                                        td : format_args!("{}", reorg.number);
+                                                        td : format_args!("{}", reorg.depth);
+                                                        td : &reorg.old_tip[..4];
+                                                        td : &reorg.new_tip[..4];
+                                                        td : reorg.abandoned.iter().map(|hash| &hash[..4]).collect::<Vec<_>>().join(" ");
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        h3 : "Balances at tip";
+                                        table {
+                                            tr {
+                                                th : "Account";
+                                                @ for id in 0..stores.len() {
+                                                    th(class=format_args!("node{}", id)) : format_args!("node{}", id);
+                                                }
+                                            }
+                                            tr {
+                                                td : "state root";
+                                                @ for id in 0..stores.len() as u8 {
+                                                    @ if let Some(state) = snapshots[&id].tip_state.as_ref() {
+                                                        td : format_args!("{}", &hex::encode(state.root())[..4]);
+                                                    } else {
+                                                        td : "-";
+                                                    }
+                                                }
+                                            }
+                                            @ for account in 0..stores.len() as u8 {
+                                                tr {
+                                                    td(class=format_args!("node{}", account)) : format_args!("{}", account);
+                                                    @ for id in 0..stores.len() as u8 {
+                                                        @ if let Some(state) = snapshots[&id].tip_state.as_ref() {
+                                                            td : format_args!("{}", state.balance(account));
                                                         } else {
                                                             td ;
                                                         }
@@ -174,10 +480,273 @@ impl Server {
                             });
                             serve_string!(req, page)
                         }
+                        "/diff" => {
+                            let node = |name: &str| params.get(name).and_then(|id| id.parse::<u8>().ok()).filter(|id| stores.contains_key(id));
+                            let (a, b) = match (node("a"), node("b")) {
+                                (Some(a), Some(b)) if a != b => (a, b),
+                                _ => {
+                                    serve_json_error!(req, 400, "expect two different node ids a and b");
+                                    return;
+                                }
+                            };
+                            let diff = {
+                                let read_a = stores[&a].load();
+                                let read_b = stores[&b].load();
+                                Diff::new(&read_a, &read_b)
+                            };
+                            let short = |block: &block::SealedBlock| hex::encode(block.digest())[..4].to_string();
+                            let page = format!("{}", html! {
+                                : doctype::HTML;
+                                html {
+                                    head {
+                                        title : format_args!("node{} vs node{}", a, b);
+                                        style {
+                                            : r".node0{color:green}.node1{color:blue}.node2{color:red}.node3{color:cyan}.node4{color:yellow}.node5{color:magenta}";
+                                            : r"table, th, td { border: 1px solid black; }"
+                                        }
+                                    }
+                                    body {
+                                        p {
+                                            : "Compare ";
+                                            @ for id in 0..stores.len() as u8 {
+                                                @ if id != a && id != b {
+                                                    a(href=format_args!("diff?a={}&b={}", a, id), class=format_args!("node{}", id)) : format_args!("node{} ", id);
+                                                }
+                                            }
+                                            : format_args!(" with node{}", a);
+                                        }
+                                        table {
+                                            tr {
+                                                th ;
+                                                th(class=format_args!("node{}", a)) : format_args!("node{}", a);
+                                                th(class=format_args!("node{}", b)) : format_args!("node{}", b);
+                                            }
+                                            tr {
+                                                td : "tip";
+                                                td : format_args!("{} at level {}", short(&diff.a_tip), diff.a_tip.number);
+                                                td : format_args!("{} at level {}", short(&diff.b_tip), diff.b_tip.number);
+                                            }
+                                            tr {
+                                                td : "only here";
+                                                td {
+                                                    @ for block in diff.only_a.iter() {
+                                                        div(class=format_args!("node{}", block.miner), title=format_args!("{}", block)) : format_args!("{}: {}", block.number, short(block));
+                                                    }
+                                                }
+                                                td {
+                                                    @ for block in diff.only_b.iter() {
+                                                        div(class=format_args!("node{}", block.miner), title=format_args!("{}", block)) : format_args!("{}: {}", block.number, short(block));
+                                                    }
+                                                }
+                                            }
+                                        }
+                                        @ if let Some(ancestor) = &diff.ancestor {
+                                            p : format_args!("Common ancestor {} at level {}", short(ancestor), ancestor.number);
+                                        } else {
+                                            p : "No common ancestor yet";
+                                        }
+                                        @ if diff.a_tip == diff.b_tip {
+                                            p : "Same tip";
+                                        }
+                                        @ if let Some(diverged) = diff.diverged_secs {
+                                            p : format_args!("Diverged for {} s", diverged);
+                                        }
+                                    }
+                                }
+                            });
+                            serve_string!(req, page)
+                        }
+                        "/tree" => {
+                            let at = match at_param(&params) {
+                                Ok(at) => at,
+                                Err(message) => {
+                                    serve_json_error!(req, 400, message);
+                                    return;
+                                }
+                            };
+                            let replayed = at.map(|at| history.replay(at));
+                            let last_number = match &replayed {
+                                Some(trees) => trees.values().map(|tree| tree.tip.number).max().unwrap_or_default(),
+                                None => stores.values().map(|store| store.load().tip.number).max().expect("Error when find max tip"),
+                            };
+                            let (from, to) = match window(&params, last_number) {
+                                Ok(window) => window,
+                                Err(message) => {
+                                    serve_json_error!(req, 400, message);
+                                    return;
+                                }
+                            };
+                            let ids: Vec<u8> = match params.get("node") {
+                                Some(node) => match node.parse::<u8>().ok().filter(|id| stores.contains_key(id)) {
+                                    Some(id) => vec![id],
+                                    None => {
+                                        serve_json_error!(req, 404, "node not found");
+                                        return;
+                                    }
+                                },
+                                None => (0..stores.len() as u8).collect(),
+                            };
+                            let views = ids.into_iter().filter_map(|id| match &replayed {
+                                Some(trees) => trees.get(&id).map(|tree| svg::node_view(id, tree, from, to)),
+                                None => {
+                                    let read = stores.get(&id).unwrap().load();
+                                    Some(svg::node_view(id, &read, from, to))
+                                }
+                            }).collect();
+                            let (blocks, highlights) = svg::merge(views);
+                            let image = svg::render(&blocks, &highlights);
+                            serve_svg!(req, image)
+                        }
+                        "/events" => {
+                            // server-sent events: raw response without a length, one event per message
+                            let receiver = events.subscribe();
+                            let mut writer = req.into_writer();
+                            let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+                            if writer.write_all(head.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                                return;
+                            }
+                            loop {
+                                let message = match receiver.recv_timeout(Duration::from_secs(15)) {
+                                    Ok(record) => format!("event: {}\ndata: {}\n\n", record.event.name(), serde_json::to_string(&record).expect("Json serialize error")),
+                                    Err(RecvTimeoutError::Timeout) => ": keep-alive\n\n".to_string(),
+                                    Err(RecvTimeoutError::Disconnected) => return,
+                                };
+                                // stop once the client is gone
+                                if writer.write_all(message.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                        "/metrics" => {
+                            let content_type = "Content-Type: text/plain; version=0.0.4".parse::<Header>().unwrap();
+                            let resp = Response::from_string(metrics.render(&stores))
+                                .with_header(content_type);
+                            req.respond(resp).unwrap();
+                        }
+                        "/stats" => {
+                            let records = history.records();
+                            let duration = history.duration_ms() as f64 / 1000.0;
+                            let mut ids: Vec<&u8> = stores.keys().collect();
+                            ids.sort();
+                            let reads: Vec<_> = ids.iter().map(|id| stores[id].load()).collect();
+                            let trees: Vec<&BlockTree> = reads.iter().map(|read| &**read).collect();
+                            let analysis = Analysis::new(&trees, &records, duration);
+                            drop(reads);
+                            serve_json!(req, serde_json::to_string_pretty(&analysis).expect("Json serialize error"))
+                        }
                         "/delay" => {
                             let pretty_delay: Vec<(u8,u8,u64)> = delay.iter().map(|((i,j),k)| (*i,*j,*k)).collect();
                             serve_json!(req, serde_json::to_string_pretty(&pretty_delay).expect("Json serialize error"))
                         }
+                        "/proof" => {
+                            let block_hash = params.get("block").and_then(|h| block::BlockHash::from_hex(h));
+                            let tx_id = params.get("tx").and_then(|h| hex::decode(h).ok());
+                            let node = params.get("node").and_then(|n| n.parse::<u8>().ok());
+                            let (block_hash, tx_id) = match (block_hash, tx_id) {
+                                (Some(block_hash), Some(tx_id)) => (block_hash, tx_id),
+                                _ => {
+                                    serve_json_error!(req, 400, "expect hex parameters block and tx");
+                                    return;
+                                }
+                            };
+                            // the body comes from the requested node, or the first one that has it
+                            let found = (0..stores.len() as u8).filter(|id| node.map_or(true, |n| n == *id)).find_map(|id| {
+                                let read = stores.get(&id).unwrap().load();
+                                let block = read.get(&block_hash)?;
+                                let (tx, proof) = block.merkle_proof(&tx_id)?;
+                                Some((id, block.tx_root.clone(), tx, proof))
+                            });
+                            let (served_by, tx_root, tx, proof) = match found {
+                                Some(found) => found,
+                                None => {
+                                    serve_json_error!(req, 404, "block or transaction not found");
+                                    return;
+                                }
+                            };
+                            // verify like a light client would, against each node's copy of the header
+                            let verified_by: Vec<u8> = (0..stores.len() as u8).filter(|id| {
+                                let read = stores.get(id).unwrap().load();
+                                read.get(&block_hash).map_or(false, |header| header.verify_proof(&tx, &proof))
+                            }).collect();
+                            let body = serde_json::json!({
+                                "block": hex::encode(block_hash),
+                                "served_by": served_by,
+                                "tx": tx,
+                                "tx_id": hex::encode(&tx_id),
+                                "index": proof.index,
+                                "leaves": proof.leaves,
+                                "siblings": proof.siblings.iter().map(hex::encode).collect::<Vec<_>>(),
+                                "tx_root": hex::encode(&tx_root),
+                                "verified_by": verified_by,
+                            });
+                            serve_json!(req, serde_json::to_string_pretty(&body).expect("Json serialize error"))
+                        }
+                        "/export" => {
+                            let format = match Format::from_name(params.get("format").map(String::as_str).unwrap_or("json")) {
+                                Some(format) => format,
+                                None => {
+                                    serve_json_error!(req, 400, "format should be json or bincode");
+                                    return;
+                                }
+                            };
+                            let node = params.get("node").and_then(|n| n.parse::<u8>().ok());
+                            let nodes = (0..stores.len() as u8).filter(|id| node.map_or(true, |n| n == *id)).map(|id| {
+                                let read = stores.get(&id).unwrap().load();
+                                TreeExport::new(id, &read)
+                            }).collect();
+                            let export = SimulationExport {
+                                nodes,
+                                delay: delay.iter().map(|((i,j),k)| (*i,*j,*k)).collect(),
+                            };
+                            let (content_type, extension) = match format {
+                                Format::Json => ("application/json", "json"),
+                                Format::Bincode => ("application/octet-stream", "bin"),
+                            };
+                            let content_type = format!("Content-Type: {}", content_type).parse::<Header>().unwrap();
+                            let disposition = format!("Content-Disposition: attachment; filename=\"chain.{}\"", extension).parse::<Header>().unwrap();
+                            let resp = Response::from_data(format.encode(&export).expect("Export serialize error"))
+                                .with_header(content_type)
+                                .with_header(disposition);
+                            req.respond(resp).unwrap();
+                        }
+                        path if path.starts_with("/control/") => {
+                            let result = match path {
+                                "/control/pause" => {
+                                    control.pause();
+                                    Ok(())
+                                }
+                                "/control/resume" => {
+                                    control.resume();
+                                    Ok(())
+                                }
+                                "/control/step" => control.step(),
+                                "/control/reset" => reset_config(&params, control.config())
+                                    .and_then(|config| control.reset(config).map_err(|e| e.to_string())),
+                                _ => {
+                                    serve_json_error!(req, 404, format!("{} not found", path));
+                                    return;
+                                }
+                            };
+                            if let Err(message) = result {
+                                serve_json_error!(req, 400, message);
+                                return;
+                            }
+                            let config = control.config();
+                            let body = serde_json::json!({
+                                "paused": control.paused(),
+                                "deterministic": config.deterministic,
+                                "nodes": config.nodes(),
+                                "block_int_ms": config.block_int_ms(),
+                                "my_turn_wait_ms": config.my_turn_wait_ms(),
+                            });
+                            serve_json!(req, serde_json::to_string_pretty(&body).expect("Json serialize error"))
+                        }
+                        path if path.starts_with("/api/") => {
+                            match api::route(path, &params, &stores) {
+                                Ok(body) => serve_json!(req, serde_json::to_string_pretty(&body).expect("Json serialize error")),
+                                Err((status, message)) => serve_json_error!(req, status, message),
+                            }
+                        }
                         "/" => serve_string!(req, format!("{}", html! {
                                 : doctype::HTML;
                                 html {
@@ -191,9 +760,51 @@ impl Server {
                                         p {
                                             a(href="dashboard?refresh=1"): "Dashboard w auto-refresh";
                                         }
+                                        p {
+                                            a(href="dashboard?live=1"): "Dashboard w live updates";
+                                        }
+                                        p {
+                                            a(href="events"): "Event stream";
+                                        }
+                                        p {
+                                            a(href="tree"): "Merged block tree (svg)";
+                                        }
+                                        p {
+                                            a(href="metrics"): "Prometheus metrics";
+                                        }
+                                        p {
+                                            a(href="stats"): "Chain quality and consistency (json)";
+                                        }
+                                        p {
+                                            a(href="diff?a=0&b=1"): "Compare two nodes";
+                                        }
+                                        p {
+                                            : "Control: ";
+                                            a(href="control/pause"): "pause";
+                                            : " ";
+                                            a(href="control/resume"): "resume";
+                                            : " ";
+                                            a(href="control/step"): "step";
+                                            : " ";
+                                            a(href="control/reset"): "reset";
+                                            : " ";
+                                            a(href="control/reset?deterministic=1"): "reset deterministic";
+                                        }
                                         p {
                                             a(href="delay"): "Check delay (json)";
                                         }
+                                        p {
+                                            a(href="api/nodes"): "Nodes and tips (json)";
+                                        }
+                                        p {
+                                            a(href="api/forks"): "Forks (json)";
+                                        }
+                                        p {
+                                            a(href="export?format=json"): "Export all nodes (json)";
+                                        }
+                                        p {
+                                            a(href="export?format=bincode"): "Export all nodes (bincode)";
+                                        }
                                     }
                                 }
                             }
@@ -206,8 +817,13 @@ impl Server {
                             req.respond(resp).expect("respond error");
                         }
                     }
-                });
+                }));
+            }
+            // unblocked: let the requests in flight finish
+            for request in requests {
+                let _ = request.join();
             }
         });
+        ServerHandle { http: handle, thread }
     }
 }
\ No newline at end of file
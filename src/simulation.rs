@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use crate::block::SealedBlock;
+use crate::block_tree::{BlockTree, SharedTree};
+use crate::control::Command;
+use crate::engine::Engine;
+use crate::events::{EventBus, Record};
+use crate::export;
+use crate::light_client::{LightClient, HeaderTree};
+use crate::miner::{self, Miner};
+use crate::network::Network;
+use crate::observer::SimulationObserver;
+
+pub const N: u8 = 6;
+// light clients get the ids right after the miners
+pub const LIGHT_N: u8 = 1;
+
+/// What a simulation starts from.
+#[derive(Default, Clone)]
+pub struct Config {
+    // keep each node's blocks under this directory and recover them on start
+    pub data_dir: Option<PathBuf>,
+    // start the nodes from a chain dumped by /export (.json or bincode)
+    pub import: Option<PathBuf>,
+    // override N and the miners' timing
+    pub nodes: Option<u8>,
+    pub block_int_ms: Option<u64>,
+    pub my_turn_wait_ms: Option<u64>,
+    pub delay: HashMap<(u8,u8), u64>,
+    // run on a virtual clock in one thread instead, see `Engine`; no light clients then
+    pub deterministic: bool,
+}
+
+impl Config {
+    pub fn nodes(&self) -> u8 {
+        self.nodes.unwrap_or(N)
+    }
+
+    pub fn block_int_ms(&self) -> u64 {
+        self.block_int_ms.unwrap_or(miner::BLOCK_INT_MS)
+    }
+
+    pub fn my_turn_wait_ms(&self) -> u64 {
+        self.my_turn_wait_ms.unwrap_or(miner::MY_TURN_WAIT_MS)
+    }
+}
+
+/// Every link `i -> i+1` of the ring gets `delay_ms`.
+pub fn ring_delay(nodes: u8, delay_ms: u64) -> HashMap<(u8,u8), u64> {
+    if delay_ms == 0 {
+        return HashMap::new();
+    }
+    (0..nodes).map(|i| ((i, (i + 1) % nodes), delay_ms)).collect()
+}
+
+/// The threads of a running simulation and the trees they fill.
+pub struct Simulation {
+    pub config: Config,
+    pub stores: HashMap<u8, SharedTree>,
+    pub light_stores: HashMap<u8, Arc<RwLock<HeaderTree>>>,
+    pub events: EventBus,
+    // cleared to stop the miners, light clients and network
+    running: Arc<AtomicBool>,
+    handles: Vec<JoinHandle<()>>,
+    controls: Vec<Sender<Command>>,
+}
+
+impl Simulation {
+    pub fn start(config: Config, events: EventBus) -> Result<Simulation, Box<dyn Error>> {
+        if !(2..=200).contains(&config.nodes()) {
+            return Err("nodes must be between 2 and 200".into());
+        }
+        let running = Arc::new(AtomicBool::new(true));
+        let mut handles = vec![];
+        let mut controls = vec![];
+        let mut stores = HashMap::new();
+        if let Some(dir) = &config.data_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let import = match &config.import {
+            Some(path) => Some(export::load(path)?),
+            None => None,
+        };
+        let n = config.nodes();
+        let mut trees = vec![];
+        for id in 0..n {
+            let mut block_tree = BlockTree::new(id, events.clone());
+            if let Some(dir) = &config.data_dir {
+                block_tree = block_tree.with_store(&dir.join(format!("node{}.log", id)))?;
+            }
+            if let Some(tree) = import.as_ref().and_then(|import| import.tree_for(id)) {
+                tree.load_into(&mut block_tree);
+            }
+            trees.push(block_tree);
+        }
+        let mut light_stores = HashMap::new();
+
+        if config.deterministic {
+            let trees = trees.into_iter().enumerate().map(|(id, block_tree)| (id as u8, block_tree)).collect();
+            let engine = Engine::new(trees, config.delay.clone(), config.block_int_ms(), config.my_turn_wait_ms(), events.clone());
+            stores = engine.shared.clone();
+            let (control, control_receiver) = channel();
+            controls.push(control);
+            handles.push(engine.start(running.clone(), control_receiver));
+        } else {
+            let (sender, receiver) = channel();
+            let mut senders: HashMap<u8, Sender<SealedBlock>> = Default::default();
+            for (id, block_tree) in trees.into_iter().enumerate() {
+                let id = id as u8;
+                let (sender_2, receiver_2) = channel();
+                senders.insert(id, sender_2);
+                let (control, control_receiver) = channel();
+                controls.push(control);
+                let (miner, store) = Miner::new(id, n, block_tree, sender.clone(), receiver_2, running.clone(), control_receiver);
+                let miner = miner.with_timing(config.block_int_ms(), config.my_turn_wait_ms());
+                stores.insert(id, store);
+                handles.push(miner.start());
+            }
+            let mut light_senders = HashMap::new();
+            for id in n..n + LIGHT_N {
+                let (sender_2, receiver_2) = channel();
+                light_senders.insert(id, sender_2);
+                let (client, store) = LightClient::new(id, events.clone(), receiver_2, running.clone());
+                light_stores.insert(id, store);
+                handles.push(client.start());
+            }
+            let (control, control_receiver) = channel();
+            controls.push(control);
+            let network = Network {
+                n,
+                from_miners: receiver,
+                senders,
+                light_senders,
+                artificial_delay: config.delay.clone(),
+                events: events.clone(),
+                running: running.clone(),
+                control: control_receiver,
+            };
+            handles.push(network.start());
+        }
+        Ok(Simulation { config, stores, light_stores, events, running, handles, controls })
+    }
+
+    /// Pass `command` to every thread.
+    pub fn send(&self, command: Command) {
+        for control in self.controls.iter() {
+            // a thread that is gone doesn't need it
+            let _ = control.send(command);
+        }
+    }
+
+    /// Stop every thread and wait for them, then get the stores on disk; the trees stay readable.
+    /// Threads that panicked and stores that couldn't be written are reported together.
+    pub fn stop(&mut self) -> Result<(), Box<dyn Error>> {
+        // hanging up wakes the paused threads
+        self.controls.clear();
+        self.running.store(false, Ordering::Relaxed);
+        let mut errors = vec![];
+        for handle in self.handles.drain(..) {
+            let name = handle.thread().name().unwrap_or("simulation thread").to_string();
+            if handle.join().is_err() {
+                errors.push(format!("{} panicked", name));
+            }
+        }
+        for (id, store) in self.stores.iter() {
+            if let Some(store) = store.load().store.as_ref() {
+                if let Err(e) = store.lock().unwrap_or_else(|e| e.into_inner()).sync() {
+                    errors.push(format!("block store of node {}: {}", id, e));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; ").into())
+        }
+    }
+}
+
+// a harness that bails out early doesn't leave the threads running; this may run while
+// unwinding, so it must not panic
+impl Drop for Simulation {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            eprintln!("Stopping the simulation: {}", e);
+        }
+    }
+}
+
+/// Sets up a simulation for code that embeds it; `start` runs it and hands back its stores and
+/// event bus.
+#[derive(Default)]
+pub struct SimulationBuilder {
+    config: Config,
+    events: EventBus,
+}
+
+impl SimulationBuilder {
+    pub fn new() -> SimulationBuilder {
+        Default::default()
+    }
+
+    /// Start from a whole config, e.g. the command line's.
+    pub fn config(mut self, config: Config) -> SimulationBuilder {
+        self.config = config;
+        self
+    }
+
+    pub fn nodes(mut self, nodes: u8) -> SimulationBuilder {
+        self.config.nodes = Some(nodes);
+        self
+    }
+
+    /// Artificial delay in ms of every link, replacing what was set before.
+    pub fn delay(mut self, delay: HashMap<(u8,u8), u64>) -> SimulationBuilder {
+        self.config.delay = delay;
+        self
+    }
+
+    /// Artificial delay in ms of the link `from -> to`.
+    pub fn link_delay(mut self, from: u8, to: u8, delay_ms: u64) -> SimulationBuilder {
+        self.config.delay.insert((from, to), delay_ms);
+        self
+    }
+
+    pub fn block_int_ms(mut self, block_int_ms: u64) -> SimulationBuilder {
+        self.config.block_int_ms = Some(block_int_ms);
+        self
+    }
+
+    pub fn my_turn_wait_ms(mut self, my_turn_wait_ms: u64) -> SimulationBuilder {
+        self.config.my_turn_wait_ms = Some(my_turn_wait_ms);
+        self
+    }
+
+    pub fn deterministic(mut self, deterministic: bool) -> SimulationBuilder {
+        self.config.deterministic = deterministic;
+        self
+    }
+
+    pub fn data_dir(mut self, dir: impl Into<PathBuf>) -> SimulationBuilder {
+        self.config.data_dir = Some(dir.into());
+        self
+    }
+
+    pub fn import(mut self, file: impl Into<PathBuf>) -> SimulationBuilder {
+        self.config.import = Some(file.into());
+        self
+    }
+
+    /// Publish on `events` instead of a bus of its own, to share it with other code. Set it before
+    /// adding hooks or subscribing.
+    pub fn events(mut self, events: EventBus) -> SimulationBuilder {
+        self.events = events;
+        self
+    }
+
+    /// Call `hook` with every event of the run, see `EventBus::hook`.
+    pub fn hook(self, hook: impl FnMut(&Record) + Send + 'static) -> SimulationBuilder {
+        self.events.hook(hook);
+        self
+    }
+
+    /// Add `observer`, see `SimulationObserver`. Several can be added.
+    pub fn observer(self, observer: impl SimulationObserver + 'static) -> SimulationBuilder {
+        self.events.observe(observer);
+        self
+    }
+
+    /// The bus the run will publish on.
+    pub fn event_bus(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Every event from the start of the run on.
+    pub fn subscribe(&self) -> Receiver<Record> {
+        self.events.subscribe()
+    }
+
+    pub fn start(self) -> Result<Simulation, Box<dyn Error>> {
+        Simulation::start(self.config, self.events)
+    }
+}
@@ -0,0 +1,55 @@
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use crate::block::Block;
+use crate::transaction::Transaction;
+
+/// Coins credited to the miner of every block except genesis.
+pub const BLOCK_REWARD: u64 = 10;
+
+/// Account balances and nonces after applying a block. `BTreeMap` keeps the serialization (and
+/// therefore the state root) independent of insertion order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct State {
+    pub balances: BTreeMap<u8, u64>,
+    pub nonces: BTreeMap<u8, u64>,
+}
+
+impl State {
+    pub fn balance(&self, account: u8) -> u64 {
+        self.balances.get(&account).copied().unwrap_or_default()
+    }
+
+    pub fn nonce(&self, account: u8) -> u64 {
+        self.nonces.get(&account).copied().unwrap_or_default()
+    }
+
+    /// Apply a single transfer. Returns false and leaves the state untouched if the nonce is wrong
+    /// or the sender can't afford it.
+    pub fn apply_transaction(&mut self, tx: &Transaction) -> bool {
+        if tx.nonce != self.nonce(tx.from) || tx.value > self.balance(tx.from) {
+            return false;
+        }
+        *self.balances.entry(tx.from).or_default() -= tx.value;
+        *self.balances.entry(tx.to).or_default() += tx.value;
+        *self.nonces.entry(tx.from).or_default() += 1;
+        true
+    }
+
+    /// Credit the block reward and apply the transactions in order. Invalid transactions are skipped.
+    pub fn apply(&mut self, miner: u8, transactions: &[Transaction]) {
+        *self.balances.entry(miner).or_default() += BLOCK_REWARD;
+        for tx in transactions {
+            self.apply_transaction(tx);
+        }
+    }
+
+    pub fn apply_block(&mut self, block: &Block) {
+        self.apply(block.miner, &block.transactions);
+    }
+
+    pub fn root(&self) -> Vec<u8> {
+        let serialized = bincode::serialize(self).unwrap();
+        let digest = ring::digest::digest(&ring::digest::SHA256, &serialized);
+        digest.as_ref().to_vec()
+    }
+}
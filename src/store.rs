@@ -0,0 +1,84 @@
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::block::Block;
+
+// length, its checksum and the block's checksum
+const HEADER: usize = 12;
+
+/// First four bytes of the SHA-256 of `bytes`.
+fn checksum(bytes: &[u8]) -> [u8; 4] {
+    ring::digest::digest(&ring::digest::SHA256, bytes).as_ref()[..4].try_into().unwrap()
+}
+
+/// Append-only log of blocks, in the order they were inserted into the tree. Each record is the
+/// length of the bincode encoded block (u32 big endian), a checksum of the length, a checksum of
+/// the block and then the block. The length has its own checksum so a damaged one can't pass for
+/// a record cut short.
+pub struct BlockStore {
+    file: File,
+    // the first append that failed; nothing is appended after it, and `sync` reports it
+    failed: Option<io::Error>,
+}
+
+impl BlockStore {
+    /// Open (or create) the log and read back every block in it. A last record cut short by a
+    /// crash is dropped and the file truncated, so new blocks are appended after the last complete
+    /// one. Anything else that doesn't check out is an error and the file is left as it is.
+    pub fn open(path: &Path) -> io::Result<(BlockStore, Vec<Block>)> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        let corrupt = |at: usize| io::Error::new(io::ErrorKind::InvalidData, format!("{}: corrupt block record at byte {}", path.display(), at));
+        let mut blocks = vec![];
+        let mut end = 0;
+        // a header cut short can only be the last record
+        while bytes.len() - end >= HEADER {
+            let header = &bytes[end..end + HEADER];
+            if checksum(&header[..4]) != header[4..8] {
+                return Err(corrupt(end));
+            }
+            let len = u32::from_be_bytes(header[..4].try_into().unwrap()) as usize;
+            // the length is good, so a block running past the end was cut short
+            let record = match bytes.get(end + HEADER..end + HEADER + len) {
+                Some(record) => record,
+                None => break,
+            };
+            if checksum(record) != header[8..12] {
+                return Err(corrupt(end));
+            }
+            let block: Block = bincode::deserialize(record).map_err(|_| corrupt(end))?;
+            blocks.push(block);
+            end += HEADER + len;
+        }
+        file.set_len(end as u64)?;
+        file.seek(SeekFrom::Start(end as u64))?;
+        Ok((BlockStore { file, failed: None }, blocks))
+    }
+
+    pub fn append(&mut self, block: &Block) -> io::Result<()> {
+        if let Some(err) = &self.failed {
+            return Err(io::Error::new(err.kind(), err.to_string()));
+        }
+        let serialized = bincode::serialize(block).unwrap();
+        let len = (serialized.len() as u32).to_be_bytes();
+        let mut record = len.to_vec();
+        record.extend_from_slice(&checksum(&len));
+        record.extend_from_slice(&checksum(&serialized));
+        record.extend(serialized);
+        let result = self.file.write_all(&record).and_then(|_| self.file.flush());
+        if let Err(err) = &result {
+            self.failed = Some(io::Error::new(err.kind(), err.to_string()));
+        }
+        result
+    }
+
+    /// Make sure everything appended is on disk, or report the append that failed.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if let Some(err) = self.failed.take() {
+            return Err(err);
+        }
+        self.file.sync_all()
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use crate::block::{BlockHash, SealedBlock};
+use crate::block_tree::BlockTree;
+
+/// Blocks at least this deep under a tip are drawn as final.
+pub const FINALITY_DEPTH: u64 = 6;
+
+const COLORS: [&str; 6] = ["green", "blue", "red", "cyan", "yellow", "magenta"];
+const COLUMN: u64 = 70;
+const LANE: u64 = 40;
+const MARGIN: u64 = 30;
+
+/// What to emphasize in a drawing, all keyed by block digest.
+#[derive(Default)]
+pub struct Highlights {
+    // which nodes have the block as their tip
+    pub tips: HashMap<BlockHash, Vec<u8>>,
+    // drawn along the first lane, the other branches below it
+    pub canonical: HashSet<BlockHash>,
+    pub finalized: HashSet<BlockHash>,
+    // height of the tip the canonical chain leads to
+    pub height: u64,
+}
+
+/// One node's tree, restricted to levels `from..=to`.
+pub fn node_view(id: u8, tree: &BlockTree, from: u64, to: u64) -> (Vec<SealedBlock>, Highlights) {
+    let mut highlights = Highlights {
+        height: tree.tip.number,
+        ..Default::default()
+    };
+    highlights.tips.insert(tree.tip.digest(), vec![id]);
+    // the tip's chain, but not further down than anything drawn, however long it is
+    let mut block = Some(&tree.tip);
+    while let Some(b) = block.filter(|b| b.number >= from.saturating_sub(FINALITY_DEPTH)) {
+        if b.number + FINALITY_DEPTH <= tree.tip.number {
+            highlights.finalized.insert(b.digest());
+        }
+        highlights.canonical.insert(b.digest());
+        block = tree.get(&b.parent);
+    }
+    let blocks = (from..=to)
+        .filter_map(|level| tree.number_block.get(&level))
+        .flat_map(|blocks| blocks.iter().cloned())
+        .collect();
+    (blocks, highlights)
+}
+
+/// The union of several nodes' views. The canonical lane follows the highest tip, and a block is
+/// final only if it is final for every node.
+pub fn merge(views: Vec<(Vec<SealedBlock>, Highlights)>) -> (Vec<SealedBlock>, Highlights) {
+    let mut merged = Highlights::default();
+    let mut blocks: HashMap<BlockHash, SealedBlock> = HashMap::new();
+    let mut finalized: Option<HashSet<BlockHash>> = None;
+    for (node_blocks, node) in views {
+        for (tip, ids) in node.tips {
+            merged.tips.entry(tip).or_default().extend(ids);
+        }
+        finalized = Some(match finalized {
+            Some(finalized) => finalized.intersection(&node.finalized).cloned().collect(),
+            None => node.finalized,
+        });
+        if merged.canonical.is_empty() || merged.height < node.height {
+            merged.canonical = node.canonical;
+            merged.height = node.height;
+        }
+        for block in node_blocks {
+            blocks.entry(block.digest()).or_insert(block);
+        }
+    }
+    merged.finalized = finalized.unwrap_or_default();
+    (blocks.into_values().collect(), merged)
+}
+
+/// Draw blocks left to right by level with an arrow from each child to its parent. A child stays
+/// in its parent's lane unless a sibling got there first, so forks branch off downwards.
+pub fn render(blocks: &[SealedBlock], highlights: &Highlights) -> String {
+    let mut levels: BTreeMap<u64, Vec<(BlockHash, &SealedBlock)>> = BTreeMap::new();
+    for block in blocks {
+        levels.entry(block.number).or_default().push((block.digest(), block));
+    }
+    let first = levels.keys().next().copied().unwrap_or_default();
+    let last = levels.keys().next_back().copied().unwrap_or_default();
+    let mut lanes: HashMap<BlockHash, u64> = HashMap::new();
+    let mut lane_count = 0;
+    let mut previous = HashSet::new();
+    for level in levels.values_mut() {
+        // canonical first, then a stable order for the rest
+        level.sort_by_key(|(digest, _)| (!highlights.canonical.contains(digest), *digest));
+        let mut taken = HashSet::new();
+        for (digest, block) in level.iter() {
+            let lane = match lanes.get(&block.parent) {
+                Some(lane) if !taken.contains(lane) => *lane,
+                // a new branch gets the first lane that is free here and was free one level back
+                _ => (0..).find(|lane| !taken.contains(lane) && !previous.contains(lane)).unwrap(),
+            };
+            taken.insert(lane);
+            lane_count = lane_count.max(lane + 1);
+            lanes.insert(*digest, lane);
+        }
+        previous = taken;
+    }
+    let x = |number: u64| MARGIN + (number - first) * COLUMN;
+    let y = |lane: u64| MARGIN + lane * LANE;
+    let width = x(last) + COLUMN + MARGIN;
+    let height = y(lane_count) + MARGIN;
+
+    let mut svg = String::new();
+    write!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace" font-size="11">"#, width, height).unwrap();
+    svg.push_str(r#"<defs><marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="gray"/></marker></defs>"#);
+    for level in levels.values() {
+        for (digest, block) in level.iter() {
+            if let (Some(lane), Some(parent_lane)) = (lanes.get(digest), lanes.get(&block.parent)) {
+                write!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="gray" marker-end="url(#arrow)"/>"#,
+                       x(block.number), y(*lane) + 10, x(block.number - 1) + 44, y(*parent_lane) + 10).unwrap();
+            }
+        }
+    }
+    for level in levels.values() {
+        for (digest, block) in level.iter() {
+            let lane = lanes[digest];
+            let (stroke, stroke_width) = if highlights.tips.contains_key(digest) {
+                ("black", 3)
+            } else if highlights.finalized.contains(digest) {
+                ("dimgray", 2)
+            } else {
+                ("none", 0)
+            };
+            let opacity = if highlights.finalized.contains(digest) { "1" } else { "0.6" };
+            write!(svg, r#"<g><title>{}</title><rect x="{}" y="{}" width="44" height="20" rx="3" fill="{}" fill-opacity="{}" stroke="{}" stroke-width="{}"/>"#,
+                   block, x(block.number), y(lane), COLORS[block.miner as usize % COLORS.len()], opacity, stroke, stroke_width).unwrap();
+            write!(svg, r#"<text x="{}" y="{}">{}</text></g>"#, x(block.number) + 6, y(lane) + 14, &hex::encode(digest)[..4]).unwrap();
+            if let Some(ids) = highlights.tips.get(digest) {
+                let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                write!(svg, r#"<text x="{}" y="{}">tip {}</text>"#, x(block.number), y(lane) + 32, ids.join(",")).unwrap();
+            }
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use crate::events::EventBus;
+use crate::report::{self, Report};
+use crate::simulation::{self, Config};
+
+/// One combination of the swept parameters.
+#[derive(Debug, Clone, Copy)]
+pub struct Params {
+    pub nodes: u8,
+    pub ring_delay_ms: u64,
+    pub block_int_ms: u64,
+    pub my_turn_wait_ms: u64,
+}
+
+/// `start:end:step` (end included), `start:end` with a step of one, or a single value.
+fn parse_range(arg: &str) -> Result<Vec<u64>, Box<dyn Error>> {
+    let parts: Vec<u64> = arg.split(':').map(str::parse).collect::<Result<_, _>>()?;
+    let (start, end, step) = match parts[..] {
+        [value] => (value, value, 1),
+        [start, end] => (start, end, 1),
+        [start, end, step] if step > 0 => (start, end, step),
+        _ => return Err(format!("Bad range {}", arg).into()),
+    };
+    Ok((start..=end).step_by(step as usize).collect())
+}
+
+const HEADER: &str = "nodes,ring_delay_ms,block_int_ms,my_turn_wait_ms,duration_secs,canonical_height,fork_levels,stale_rate,average_block_interval_secs,time_to_agreement_secs";
+
+fn csv_row(params: &Params, report: &Report) -> String {
+    let optional = |value: Option<f64>| value.map(|value| format!("{:.3}", value)).unwrap_or_default();
+    let fork_levels = report.nodes.iter().map(|node| node.forks).max().unwrap_or(0);
+    format!(
+        "{},{},{},{},{:.1},{},{},{:.3},{},{}",
+        params.nodes,
+        params.ring_delay_ms,
+        params.block_int_ms,
+        params.my_turn_wait_ms,
+        report.duration_secs,
+        report.canonical_height,
+        fork_levels,
+        report.analysis.stale_rate,
+        optional(report.average_block_interval_secs),
+        optional(report.time_to_agreement_secs),
+    )
+}
+
+/// `sweep [--nodes R] [--delay R] [--block-int-ms R] [--turn-wait-ms R] (--duration secs | --blocks n)
+/// [--jobs n] [--out file] [--deterministic]`: run a headless simulation for every combination of the ranges and
+/// write one CSV line of metrics per run.
+pub fn sweep_command(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut nodes = vec![simulation::N as u64];
+    let mut delays = vec![0];
+    let mut block_ints = vec![crate::miner::BLOCK_INT_MS];
+    let mut turn_waits = vec![crate::miner::MY_TURN_WAIT_MS];
+    let mut duration = None;
+    let mut blocks = None;
+    let mut jobs = std::thread::available_parallelism().map_or(1, |jobs| jobs.get());
+    let mut out = None;
+    let mut deterministic = false;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} expects a value", arg));
+        match arg.as_str() {
+            "--nodes" => nodes = parse_range(value()?)?,
+            "--delay" => delays = parse_range(value()?)?,
+            "--block-int-ms" => block_ints = parse_range(value()?)?,
+            "--turn-wait-ms" => turn_waits = parse_range(value()?)?,
+            "--duration" => duration = Some(value()?.parse()?),
+            "--blocks" => blocks = Some(value()?.parse()?),
+            "--jobs" => jobs = value()?.parse()?,
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--deterministic" => deterministic = true,
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+    if duration.is_none() && blocks.is_none() {
+        return Err("sweep needs --duration or --blocks".into());
+    }
+    if nodes.iter().any(|nodes| *nodes < 2 || *nodes > 200) {
+        return Err("--nodes must be between 2 and 200".into());
+    }
+
+    let mut combinations = vec![];
+    for nodes in nodes.iter() {
+        for ring_delay_ms in delays.iter() {
+            for block_int_ms in block_ints.iter() {
+                for my_turn_wait_ms in turn_waits.iter() {
+                    combinations.push(Params {
+                        nodes: *nodes as u8,
+                        ring_delay_ms: *ring_delay_ms,
+                        block_int_ms: *block_int_ms,
+                        my_turn_wait_ms: *my_turn_wait_ms,
+                    });
+                }
+            }
+        }
+    }
+    let total = combinations.len();
+    // the runs are mostly asleep, so the jobs only bound how many threads are alive at once
+    let queue = Arc::new(Mutex::new(combinations.into_iter().enumerate().collect::<Vec<_>>()));
+    let rows = Arc::new(Mutex::new(vec![]));
+    let workers: Vec<_> = (0..jobs.max(1)).map(|_| {
+        let queue = queue.clone();
+        let rows = rows.clone();
+        std::thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().pop();
+                let (index, params) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                let config = Config {
+                    nodes: Some(params.nodes),
+                    block_int_ms: Some(params.block_int_ms),
+                    my_turn_wait_ms: Some(params.my_turn_wait_ms),
+                    delay: simulation::ring_delay(params.nodes, params.ring_delay_ms),
+                    deterministic,
+                    ..Default::default()
+                };
+                let result = report::run_batch(config, EventBus::default(), duration, blocks, None);
+                match result {
+                    Ok(report) => {
+                        eprintln!("done {:?}", params);
+                        rows.lock().unwrap().push((index, csv_row(&params, &report)));
+                    }
+                    Err(e) => eprintln!("{:?} failed: {}", params, e),
+                }
+            }
+        })
+    }).collect();
+    for worker in workers {
+        worker.join().expect("Sweep worker panicked");
+    }
+
+    let mut rows = std::mem::take(&mut *rows.lock().unwrap());
+    rows.sort();
+    let mut csv = String::from(HEADER);
+    csv.push('\n');
+    for (_, row) in rows.iter() {
+        csv.push_str(row);
+        csv.push('\n');
+    }
+    eprintln!("{} of {} runs finished", rows.len(), total);
+    match out {
+        Some(path) => std::fs::write(path, csv)?,
+        None => std::io::stdout().write_all(csv.as_bytes())?,
+    }
+    Ok(())
+}
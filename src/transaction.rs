@@ -0,0 +1,26 @@
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct Transaction {
+    pub from: u8,
+    pub to: u8,
+    pub value: u64,
+    pub nonce: u64,
+}
+
+impl Transaction {
+    pub fn new(from: u8, to: u8, value: u64, nonce: u64) -> Self {
+        Self {
+            from,
+            to,
+            value,
+            nonce,
+        }
+    }
+
+    pub fn digest(&self) -> Vec<u8> {
+        let serialized = bincode::serialize(self).unwrap();
+        let digest = ring::digest::digest(&ring::digest::SHA256, &serialized);
+        digest.as_ref().to_vec()
+    }
+}
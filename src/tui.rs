@@ -0,0 +1,116 @@
+use crossterm::{cursor, Result};
+use crossterm::event::{poll, read, Event, KeyCode, KeyModifiers};
+use crossterm::terminal::{enable_raw_mode, disable_raw_mode, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, size};
+use crossterm::style::{Color, SetForegroundColor, Print};
+use std::io::{stdout, Stdout, Write};
+use std::time::Duration;
+use crate::block::SealedBlock;
+use crate::control::Control;
+use crate::diff::Diff;
+
+const MARGIN: u16 = 4;
+const COLUMN: u16 = 30;
+
+fn color(miner: u8) -> Color {
+    let color_map = [Color::Green, Color::Blue, Color::Red, Color::Cyan, Color::Yellow, Color::Magenta];
+    color_map.get(miner as usize).copied().unwrap_or(Color::White)
+}
+
+fn short(block: &SealedBlock) -> String {
+    hex::encode(block.digest())[..4].to_string()
+}
+
+fn draw(stdout: &mut Stdout, diff: &Diff, status: &str, rows: u16) -> Result<()> {
+    queue!(stdout, Clear(ClearType::All), cursor::MoveTo(0,0), SetForegroundColor(Color::White),
+        Print(format!("left/right: node a, up/down: node b, p: pause/resume, s: step, r: reset, q: quit  {}", status)))?;
+    let ancestor = match &diff.ancestor {
+        Some(ancestor) => format!("common ancestor {} at level {}", short(ancestor), ancestor.number),
+        None => "no common ancestor yet".to_string(),
+    };
+    let diverged = match diff.diverged_secs {
+        Some(secs) => format!(", diverged for {} s", secs),
+        None if diff.a_tip == diff.b_tip => ", same tip".to_string(),
+        None => String::new(),
+    };
+    queue!(stdout, cursor::MoveTo(0,1), Print(format!("{}{}", ancestor, diverged)))?;
+    for (column, (id, tip, only)) in [(diff.a, &diff.a_tip, &diff.only_a), (diff.b, &diff.b_tip, &diff.only_b)].iter().enumerate() {
+        let x = column as u16 * COLUMN;
+        queue!(stdout, cursor::MoveTo(x,2), SetForegroundColor(color(*id)), Print(format!("node{}", id)),
+            SetForegroundColor(Color::White), Print(format!(" tip {} at {}", short(tip), tip.number)))?;
+        queue!(stdout, cursor::MoveTo(x,3), Print(format!("{} blocks only here", only.len())))?;
+        // the newest ones, if they don't all fit
+        let fit = rows.saturating_sub(MARGIN) as usize;
+        for (row, block) in only.iter().skip(only.len().saturating_sub(fit)).enumerate() {
+            queue!(stdout, cursor::MoveTo(x,MARGIN + row as u16), SetForegroundColor(color(block.miner)),
+                Print(format!("{}: {}", block.number, short(block))))?;
+        }
+    }
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Show how two nodes' trees differ until `q` or Ctrl-C is pressed, redrawing twice a second.
+pub fn run(control: &Control) -> Result<()> {
+    let mut stdout = stdout();
+    enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+    let result = show(control, &mut stdout);
+    // give the terminal back even if drawing failed
+    execute!(stdout, LeaveAlternateScreen, cursor::Show)?;
+    disable_raw_mode()?;
+    result
+}
+
+fn show(control: &Control, stdout: &mut Stdout) -> Result<()> {
+    let (mut a, mut b) = (0, 1);
+    let mut message = String::new();
+    loop {
+        // a reset may have changed the number of nodes
+        let stores = control.stores();
+        let n = stores.len() as u8;
+        a %= n;
+        b %= n;
+        let diff = {
+            let read_a = stores[&a].load();
+            if a == b {
+                Diff::new(&read_a, &read_a)
+            } else {
+                Diff::new(&read_a, &stores[&b].load())
+            }
+        };
+        let status = format!("{}{}", if control.paused() { "[paused] " } else { "" }, message);
+        let (_, rows) = size()?;
+        draw(stdout, &diff, &status, rows)?;
+        if poll(Duration::from_millis(500))? {
+            if let Event::Key(key) = read()? {
+                message.clear();
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    // raw mode turns Ctrl-C into a key press instead of a signal
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Right => a = (a + 1) % n,
+                    KeyCode::Left => a = (a + n - 1) % n,
+                    KeyCode::Up => b = (b + 1) % n,
+                    KeyCode::Down => b = (b + n - 1) % n,
+                    KeyCode::Char('p') if control.paused() => control.resume(),
+                    KeyCode::Char('p') => control.pause(),
+                    KeyCode::Char('s') => {
+                        if let Err(e) = control.step() {
+                            message = e;
+                        }
+                    }
+                    KeyCode::Char('r') => {
+                        let mut config = control.config();
+                        config.data_dir = None;
+                        config.import = None;
+                        if let Err(e) = control.reset(config) {
+                            message = e.to_string();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(())
+}
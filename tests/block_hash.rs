@@ -0,0 +1,94 @@
+//! Test vectors for the canonical header encoding and block hash, see `Header::canonical_bytes`.
+//! The expected values were computed outside the crate (Python's `struct` and `hashlib`) from the
+//! documented layout, so any other implementation can check itself against them.
+
+use crossterm_blockchain_dashboard::block::{self, Block, BlockHash, Header, SealedBlock};
+
+fn header() -> Header {
+    Header {
+        miner: 3,
+        number: 7,
+        timestamp: 10171,
+        parent: BlockHash([0x11; 32]),
+        state_root: vec![0x22; 32],
+        tx_root: vec![0x33; 32],
+        creator_signature: vec![0x44; 64],
+        verifier_signature: None,
+    }
+}
+
+#[test]
+fn canonical_bytes() {
+    let expected = concat!(
+        "03", "0000000000000007", "00000000000027bb",
+        "1111111111111111111111111111111111111111111111111111111111111111",
+        "00000020", "2222222222222222222222222222222222222222222222222222222222222222",
+        "00000020", "3333333333333333333333333333333333333333333333333333333333333333",
+        "00000040", "44444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444444",
+    );
+    assert_eq!(hex::encode(header().canonical_bytes()), expected);
+    // the signing bytes are the same without the signature
+    assert_eq!(hex::encode(header().signing_bytes()), &expected[..expected.len() - 8 - 128]);
+}
+
+#[test]
+fn digest() {
+    assert_eq!(header().digest().to_string(), "3b7574c682e628af4207c048a7787e785a7f222a4013c5e7bee206ea99951b26");
+}
+
+#[test]
+fn empty_fields() {
+    let header = Header::default();
+    assert_eq!(hex::encode(header.canonical_bytes()), format!("00{}{}{}", "00".repeat(16), "00".repeat(32), "00".repeat(12)));
+    assert_eq!(header.digest().to_string(), "c6e26c3e31bac75ea556356cbbd12190e29f277ea5f9010f8f88d5ab3363a2cf");
+}
+
+#[test]
+fn genesis() {
+    let genesis = Block::genesis();
+    assert_eq!(hex::encode(&genesis.state_root), "374708fff7719dd5979ec875d56cd2286f6d3cf7ec317a3b25632aab28ec37bb");
+    assert_eq!(hex::encode(&genesis.tx_root), "0000000000000000000000000000000000000000000000000000000000000000");
+    assert_eq!(genesis.digest().to_string(), "aadf0e7387a64a5563b385b2054cad7d809cbc95a04117e127940af11249f3d0");
+}
+
+#[test]
+fn verifier_signature_is_not_hashed() {
+    let mut signed = header();
+    signed.verifier_signature = Some(vec![0x55; 64]);
+    assert_eq!(signed.digest(), header().digest());
+    // the creator's signature is
+    assert_ne!(Header { creator_signature: vec![], ..header() }.digest(), header().digest());
+}
+
+#[test]
+fn block_and_header_agree() {
+    let genesis = SealedBlock::genesis();
+    let mut block = Block::new(1, &genesis, vec![], vec![0x22; 32], 10102);
+    block.sign(&block::miner_key(1));
+    assert!(block.header().verify_signature());
+    assert_eq!(block.parent, Block::genesis().digest());
+    assert_eq!(block.digest(), block.header().digest());
+    assert_eq!(SealedBlock::new(block.clone()).unwrap().digest(), block.digest());
+    assert_eq!(BlockHash::from_hex(&block.digest().to_string()), Some(block.digest()));
+}
+
+#[test]
+fn sealing_checks_the_block() {
+    let genesis = SealedBlock::genesis();
+    let mut block = Block::new(1, &genesis, vec![], vec![0x22; 32], 10102);
+    // unsigned
+    assert!(SealedBlock::new(block.clone()).is_err());
+    block.sign(&block::miner_key(2));
+    assert!(SealedBlock::new(block.clone()).is_err());
+    block.sign(&block::miner_key(1));
+    assert!(SealedBlock::new(block.clone()).is_ok());
+    // a body the header doesn't commit to
+    let mut tampered = block.clone();
+    tampered.tx_root = vec![0x33; 32];
+    tampered.sign(&block::miner_key(1));
+    assert!(SealedBlock::new(tampered.clone()).is_err());
+    // nor does it get in through deserialization
+    let json = serde_json::to_string(&tampered).unwrap();
+    assert!(serde_json::from_str::<SealedBlock>(&json).is_err());
+    assert!(serde_json::from_str::<SealedBlock>(&serde_json::to_string(&block).unwrap()).is_ok());
+}
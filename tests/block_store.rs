@@ -0,0 +1,117 @@
+//! Recovery of the on-disk block log after a crash or corruption.
+
+mod common;
+
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+use crossterm_blockchain_dashboard::block::{Block, SealedBlock};
+use crossterm_blockchain_dashboard::block_tree::BlockTree;
+use crossterm_blockchain_dashboard::store::BlockStore;
+use crossterm_blockchain_dashboard::EventBus;
+use common::chain;
+
+fn log_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("block-store-{}-{}.log", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Append the blocks to the log and return its length.
+fn write(path: &PathBuf, blocks: &[Block]) -> u64 {
+    let (mut store, _) = BlockStore::open(path).unwrap();
+    for block in blocks {
+        store.append(block).unwrap();
+    }
+    std::fs::metadata(path).unwrap().len()
+}
+
+fn digests(blocks: &[Block]) -> Vec<String> {
+    blocks.iter().map(|block| block.digest().to_string()).collect()
+}
+
+#[test]
+fn reads_back_what_was_appended() {
+    let path = log_path("round-trip");
+    let blocks = chain();
+    write(&path, &blocks);
+    let (_, read) = BlockStore::open(&path).unwrap();
+    assert_eq!(digests(&read), digests(&blocks));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn drops_a_torn_tail() {
+    let path = log_path("torn");
+    let blocks = chain();
+    let three = write(&path, &blocks[..3]);
+    let four = write(&path, &blocks[3..]);
+    // a crash in the middle of the last record
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(four - 10).unwrap();
+    let (mut store, read) = BlockStore::open(&path).unwrap();
+    assert_eq!(digests(&read), digests(&blocks[..3]));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), three);
+    // and appends after the last complete record
+    store.append(&blocks[3]).unwrap();
+    drop(store);
+    let (_, read) = BlockStore::open(&path).unwrap();
+    assert_eq!(digests(&read), digests(&blocks));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn refuses_a_corrupt_record() {
+    let path = log_path("corrupt");
+    let len = write(&path, &chain());
+    let mut bytes = std::fs::read(&path).unwrap();
+    // the length of the genesis state root: the 12 byte record header, then miner, number,
+    // timestamp and parent
+    let at = 12 + 1 + 8 + 8 + 32;
+    assert_eq!(bytes[at], 32);
+    bytes[at] = 0xff;
+    std::fs::write(&path, &bytes).unwrap();
+    assert_eq!(BlockStore::open(&path).err().map(|err| err.kind()), Some(std::io::ErrorKind::InvalidData));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn refuses_a_corrupt_length() {
+    let path = log_path("length");
+    let len = write(&path, &chain());
+    let mut bytes = std::fs::read(&path).unwrap();
+    // the first record now seems to run past the end of the file
+    bytes[0] = 0xff;
+    std::fs::write(&path, &bytes).unwrap();
+    assert_eq!(BlockStore::open(&path).err().map(|err| err.kind()), Some(std::io::ErrorKind::InvalidData));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), len);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn drops_a_torn_header() {
+    let path = log_path("torn-header");
+    let blocks = chain();
+    let three = write(&path, &blocks[..3]);
+    write(&path, &blocks[3..]);
+    OpenOptions::new().write(true).open(&path).unwrap().set_len(three + 5).unwrap();
+    let (_, read) = BlockStore::open(&path).unwrap();
+    assert_eq!(digests(&read), digests(&blocks[..3]));
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), three);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn tree_comes_back_with_its_tip() {
+    let path = log_path("tree");
+    let blocks = chain();
+    let mut tree = BlockTree::new(0, EventBus::default()).with_store(&path).unwrap();
+    for block in blocks.iter() {
+        tree.insert(SealedBlock::new(block.clone()).unwrap());
+    }
+    let tip = tree.tip.digest();
+    drop(tree);
+    let tree = BlockTree::new(0, EventBus::default()).with_store(&path).unwrap();
+    assert_eq!(tree.tip.digest(), tip);
+    assert_eq!(tree.tip.number, 3);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,159 @@
+//! Fork choice, state checks and reorgs of a single `BlockTree`, fed blocks by hand.
+
+mod common;
+
+use crossterm_blockchain_dashboard::block::{self, Block, SealedBlock};
+use crossterm_blockchain_dashboard::block_tree::BlockTree;
+use crossterm_blockchain_dashboard::events::Event;
+use crossterm_blockchain_dashboard::EventBus;
+use common::mine;
+
+/// A signed block whose state root is made up.
+fn bad(miner: u8, parent: &SealedBlock) -> SealedBlock {
+    let mut block = Block::new(miner, parent, vec![], vec![0x99; 32], parent.timestamp + 2);
+    block.sign(&block::miner_key(miner));
+    SealedBlock::new(block).unwrap()
+}
+
+#[test]
+fn block_before_parent_with_wrong_state_is_rejected() {
+    let events = EventBus::default();
+    let published = events.subscribe();
+    let mut tree = BlockTree::new(0, events);
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let one = mine(&tree, 1, &genesis);
+    let two = bad(2, &one);
+    let three = bad(0, &two);
+    // the bad ones arrive first and, being higher, take the tip
+    tree.insert(two.clone());
+    tree.insert(three.clone());
+    assert_eq!(tree.tip, three);
+    tree.insert(one.clone());
+    assert_eq!(tree.tip, one);
+    assert!(tree.tip_state().is_some());
+    assert!(tree.get(&two.digest()).is_none());
+    assert!(tree.get(&three.digest()).is_none());
+    assert!(!tree.number_block.contains_key(&2));
+    let rejected: Vec<String> = published.try_iter().filter_map(|record| match record.event {
+        Event::Rejected { hash, .. } => Some(hash),
+        _ => None,
+    }).collect();
+    assert_eq!(rejected, vec![two.digest().to_string(), three.digest().to_string()]);
+    // and it stays out when it comes again
+    tree.insert(two.clone());
+    assert_eq!(tree.tip, one);
+}
+
+#[test]
+fn extending_the_tip_is_not_a_reorg() {
+    let mut tree = BlockTree::new(0, EventBus::default());
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let one = mine(&tree, 1, &genesis);
+    tree.insert(one.clone());
+    let two = mine(&tree, 2, &one);
+    tree.insert(two.clone());
+    assert_eq!(tree.tip, two);
+    assert!(tree.reorgs.is_empty());
+}
+
+#[test]
+fn switching_branches_records_depth_and_abandoned_blocks() {
+    let events = EventBus::default();
+    let published = events.subscribe();
+    let mut tree = BlockTree::new(0, events);
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let a1 = mine(&tree, 1, &genesis);
+    tree.insert(a1.clone());
+    let a2 = mine(&tree, 2, &a1);
+    tree.insert(a2.clone());
+    // a longer branch from genesis
+    let b1 = mine(&tree, 0, &genesis);
+    tree.insert(b1.clone());
+    let b2 = mine(&tree, 1, &b1);
+    tree.insert(b2.clone());
+    // as high as the tip only, so it stays
+    assert_eq!(tree.tip, a2);
+    let b3 = mine(&tree, 2, &b2);
+    tree.insert(b3.clone());
+    assert_eq!(tree.tip, b3);
+    assert_eq!(tree.reorgs.len(), 1);
+    let reorg = &tree.reorgs[0];
+    assert_eq!(reorg.old_tip, a2.digest().to_string());
+    assert_eq!(reorg.new_tip, b3.digest().to_string());
+    assert_eq!(reorg.number, 3);
+    assert_eq!(reorg.depth, 2);
+    assert_eq!(reorg.abandoned, vec![a2.digest().to_string(), a1.digest().to_string()]);
+    let events: Vec<Event> = published.try_iter().map(|record| record.event).filter(|event| matches!(event, Event::Reorg { .. })).collect();
+    assert_eq!(events, vec![Event::Reorg {
+        node: 0,
+        old_tip: reorg.old_tip.clone(),
+        new_tip: reorg.new_tip.clone(),
+        depth: 2,
+        abandoned: reorg.abandoned.clone(),
+    }]);
+}
+
+#[test]
+fn reorg_to_an_orphan_tip_is_recorded_once_it_connects() {
+    let mut tree = BlockTree::new(0, EventBus::default());
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let a1 = mine(&tree, 1, &genesis);
+    tree.insert(a1.clone());
+    // mine the other branch on a scratch tree, then hand it over tip first
+    let mut scratch = BlockTree::new(1, EventBus::default());
+    scratch.insert(genesis.clone());
+    let b1 = mine(&scratch, 0, &genesis);
+    scratch.insert(b1.clone());
+    let b2 = mine(&scratch, 1, &b1);
+    scratch.insert(b2.clone());
+    let b3 = mine(&scratch, 2, &b2);
+    tree.insert(b3.clone());
+    assert_eq!(tree.tip, b3);
+    tree.insert(b1.clone());
+    assert!(tree.reorgs.is_empty());
+    tree.insert(b2.clone());
+    assert_eq!(tree.tip, b3);
+    assert_eq!(tree.reorgs.len(), 1);
+    let reorg = &tree.reorgs[0];
+    assert_eq!(reorg.old_tip, a1.digest().to_string());
+    assert_eq!(reorg.new_tip, b3.digest().to_string());
+    assert_eq!(reorg.depth, 1);
+    assert_eq!(reorg.abandoned, vec![a1.digest().to_string()]);
+}
+
+#[test]
+fn child_of_a_rejected_block_is_rejected() {
+    let events = EventBus::default();
+    let published = events.subscribe();
+    let mut tree = BlockTree::new(0, events);
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let one = mine(&tree, 1, &genesis);
+    tree.insert(one.clone());
+    let two = bad(2, &one);
+    let three = bad(0, &two);
+    let four = bad(1, &three);
+    // the grandchild comes first, then the bad block and its child
+    tree.insert(four.clone());
+    assert_eq!(tree.tip, four);
+    tree.insert(two.clone());
+    // nothing links it to the bad block yet
+    assert_eq!(tree.tip, four);
+    tree.insert(three.clone());
+    assert_eq!(tree.tip, one);
+    assert!(tree.tip_state().is_some());
+    assert!(tree.get(&three.digest()).is_none());
+    assert!(tree.get(&four.digest()).is_none());
+    let rejected: Vec<String> = published.try_iter().filter_map(|record| match record.event {
+        Event::Rejected { hash, .. } => Some(hash),
+        _ => None,
+    }).collect();
+    assert_eq!(rejected, vec![two.digest().to_string(), three.digest().to_string(), four.digest().to_string()]);
+    // and a block built on it later doesn't become the tip either
+    tree.insert(bad(2, &four));
+    assert_eq!(tree.tip, one);
+}
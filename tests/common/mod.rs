@@ -0,0 +1,30 @@
+//! Blocks for the tests that feed trees by hand.
+
+#![allow(dead_code)]
+
+use crossterm_blockchain_dashboard::block::{self, Block, SealedBlock};
+use crossterm_blockchain_dashboard::block_tree::BlockTree;
+use crossterm_blockchain_dashboard::miner::new_block;
+use crossterm_blockchain_dashboard::EventBus;
+
+/// Miners the test blocks are signed for.
+pub const N: u8 = 3;
+
+/// A valid block of `miner` on top of `parent`, which must be in `tree` with its state.
+pub fn mine(tree: &BlockTree, miner: u8, parent: &SealedBlock) -> SealedBlock {
+    let state = tree.states[&parent.digest()].clone();
+    new_block(miner, N, &block::miner_key(miner), parent, state, parent.timestamp + 2)
+}
+
+/// Genesis and three blocks on top of it.
+pub fn chain() -> Vec<Block> {
+    let mut tree = BlockTree::new(0, EventBus::default());
+    let mut blocks = vec![SealedBlock::genesis()];
+    tree.insert(SealedBlock::genesis());
+    for miner in 0..N {
+        let block = mine(&tree, miner, blocks.last().unwrap());
+        tree.insert(block.clone());
+        blocks.push(block);
+    }
+    blocks.iter().map(|block| block.block().clone()).collect()
+}
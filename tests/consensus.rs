@@ -0,0 +1,120 @@
+//! Consensus invariants on the deterministic engine, over random node counts, timings and delay
+//! matrices. proptest shrinks a failing case to a small one and saves its seed under
+//! `proptest-regressions/`, where the next run replays it first; `PROPTEST_CASES` sets how many
+//! cases each property tries.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use proptest::prelude::*;
+use crossterm_blockchain_dashboard::analysis;
+use crossterm_blockchain_dashboard::engine::Engine;
+use crossterm_blockchain_dashboard::{BlockHash, BlockTree, EventBus, SealedBlock, SimulationObserver};
+
+// virtual time each case runs for
+const RUN_MS: u64 = 120_000;
+
+#[derive(Debug, Clone)]
+struct Setup {
+    n: u8,
+    block_int_ms: u64,
+    my_turn_wait_ms: u64,
+    delay: HashMap<(u8,u8), u64>,
+}
+
+/// Whole seconds only: blocks carry their timestamp in seconds.
+fn timing() -> impl Strategy<Value = (u64, u64)> {
+    (1..4u64, 1..10u64).prop_map(|(block_int, extra)| (block_int * 1000, (block_int + extra) * 1000))
+}
+
+fn delays(n: u8, max_delay_ms: u64) -> impl Strategy<Value = HashMap<(u8,u8), u64>> {
+    let links: Vec<(u8,u8)> = (0..n).flat_map(|i| (0..n).filter(move |j| *j != i).map(move |j| (i, j))).collect();
+    proptest::collection::vec(0..=max_delay_ms, links.len())
+        .prop_map(move |delays| links.iter().copied().zip(delays).collect())
+}
+
+fn setup(max_delay_ms: u64) -> impl Strategy<Value = Setup> {
+    (2..8u8, timing()).prop_flat_map(move |(n, (block_int_ms, my_turn_wait_ms))| {
+        delays(n, max_delay_ms).prop_map(move |delay| Setup { n, block_int_ms, my_turn_wait_ms, delay })
+    })
+}
+
+/// Every tip change of every node, in order.
+#[derive(Clone, Default)]
+struct Tips(Arc<Mutex<Vec<(u8, u64)>>>);
+
+impl SimulationObserver for Tips {
+    fn on_tip_changed(&mut self, node: u8, _hash: &str, number: u64) {
+        self.0.lock().unwrap().push((node, number));
+    }
+}
+
+fn run(setup: &Setup, tips: &Tips) -> Vec<BlockTree> {
+    let events = EventBus::default();
+    events.observe(tips.clone());
+    let trees = (0..setup.n).map(|id| (id, BlockTree::new(id, events.clone()))).collect();
+    let mut engine = Engine::new(trees, setup.delay.clone(), setup.block_int_ms, setup.my_turn_wait_ms, events);
+    let until = engine.now_ms + RUN_MS;
+    engine.run_until(until);
+    (0..setup.n).map(|id| engine.trees.remove(&id).unwrap()).collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn number_is_parent_plus_one(setup in setup(20_000)) {
+        let trees = run(&setup, &Tips::default());
+        for tree in trees.iter() {
+            for block in tree.number_block.values().flat_map(|blocks| blocks.iter()) {
+                if let Some(parent) = tree.get(&block.parent) {
+                    prop_assert_eq!(block.number, parent.number + 1, "node {}", tree.id);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tips_never_regress(setup in setup(20_000)) {
+        let tips = Tips::default();
+        run(&setup, &tips);
+        let mut heights: HashMap<u8, u64> = HashMap::new();
+        for (node, number) in tips.0.lock().unwrap().iter() {
+            let height = heights.entry(*node).or_default();
+            prop_assert!(*number >= *height, "node {} went from {} to {}", node, height, number);
+            *height = *number;
+        }
+    }
+
+    #[test]
+    fn zero_delay_converges(setup in setup(0)) {
+        let trees = run(&setup, &Tips::default());
+        let chain: Vec<BlockHash> = trees[0].chain().iter().map(SealedBlock::digest).collect();
+        prop_assert!(chain.len() > 1, "nothing was mined");
+        for tree in trees.iter().skip(1) {
+            let other: Vec<BlockHash> = tree.chain().iter().map(SealedBlock::digest).collect();
+            prop_assert_eq!(&other, &chain, "node {} disagrees", tree.id);
+        }
+    }
+
+    /// With delays short enough that the next miner in the ring always hears of a block before
+    /// anyone else's turn comes, the miners take strict turns and each gets its share of the chain.
+    #[test]
+    fn chain_quality_follows_rotation(
+        (setup, max_delay_ms) in (2..8u8, timing(), 0..1000u64).prop_flat_map(|(n, (block_int_ms, my_turn_wait_ms), max_delay_ms)| {
+            // room for the turn miner's block to reach everyone, plus a second lost to timestamps
+            let my_turn_wait_ms = my_turn_wait_ms.max(block_int_ms + 2 * max_delay_ms + 1000);
+            delays(n, max_delay_ms).prop_map(move |delay| (Setup { n, block_int_ms, my_turn_wait_ms, delay }, max_delay_ms))
+        })
+    ) {
+        let trees = run(&setup, &Tips::default());
+        let trees: Vec<&BlockTree> = trees.iter().collect();
+        let chain = analysis::canonical_chain(&trees);
+        let mined: Vec<u8> = chain.iter().filter(|block| block.number > 0).map(|block| block.miner).collect();
+        prop_assert!(!mined.is_empty(), "nothing was mined with delays up to {}", max_delay_ms);
+        let share = mined.len() as f64 / setup.n as f64;
+        for id in 0..setup.n {
+            let count = mined.iter().filter(|miner| **miner == id).count() as f64;
+            prop_assert!((count - share).abs() <= 1.0, "miner {} has {} of {} blocks", id, count, mined.len());
+        }
+    }
+}
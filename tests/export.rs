@@ -0,0 +1,109 @@
+//! Exporting trees and loading them back, in both formats.
+
+mod common;
+
+use std::collections::BTreeSet;
+use crossterm_blockchain_dashboard::block::SealedBlock;
+use crossterm_blockchain_dashboard::block_tree::BlockTree;
+use crossterm_blockchain_dashboard::events::Event;
+use crossterm_blockchain_dashboard::export::{self, SimulationExport, TreeExport};
+use crossterm_blockchain_dashboard::EventBus;
+use common::mine;
+
+/// Two blocks competing at level 1, the first one seen as the tip, and one on top of the other.
+fn forked_tree(id: u8) -> BlockTree {
+    let mut tree = BlockTree::new(id, EventBus::default());
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let a = mine(&tree, 1, &genesis);
+    let b = mine(&tree, 2, &genesis);
+    tree.insert(a.clone());
+    tree.insert(b.clone());
+    let c = mine(&tree, 0, &b);
+    tree.insert(c);
+    tree
+}
+
+fn digests(tree: &BlockTree) -> BTreeSet<String> {
+    tree.number_block.values().flat_map(|blocks| blocks.iter()).map(|block| block.digest().to_string()).collect()
+}
+
+fn round_trip(extension: &str) {
+    let path = std::env::temp_dir().join(format!("export-{}.{}", std::process::id(), extension));
+    let trees = [forked_tree(0), forked_tree(1)];
+    let dump = SimulationExport {
+        nodes: trees.iter().map(|tree| TreeExport::new(tree.id, tree)).collect(),
+        delay: vec![(0, 1, 5000)],
+    };
+    export::save(&path, &dump).unwrap();
+    let loaded = export::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.delay, vec![(0, 1, 5000)]);
+    for tree in trees.iter() {
+        let mut fresh = BlockTree::new(tree.id, EventBus::default());
+        loaded.tree_for(tree.id).unwrap().load_into(&mut fresh);
+        assert_eq!(digests(&fresh), digests(tree), "node {} over {}", tree.id, extension);
+        assert_eq!(fresh.tip, tree.tip);
+        assert!(fresh.tip_state().is_some());
+    }
+}
+
+#[test]
+fn json_round_trip() {
+    round_trip("json");
+}
+
+#[test]
+fn bincode_round_trip() {
+    round_trip("bin");
+}
+
+#[test]
+fn exported_tip_wins_a_tie() {
+    let mut tree = BlockTree::new(0, EventBus::default());
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let a = mine(&tree, 1, &genesis);
+    let b = mine(&tree, 2, &genesis);
+    tree.insert(a.clone());
+    tree.insert(b.clone());
+    assert_eq!(tree.tip, a);
+    // whichever order the blocks come back in, the tip is the one that was exported
+    let dump = TreeExport { blocks: vec![genesis.clone(), b, a.clone()], ..TreeExport::new(0, &tree) };
+    let mut fresh = BlockTree::new(0, EventBus::default());
+    dump.load_into(&mut fresh);
+    assert_eq!(fresh.tip, a);
+}
+
+#[test]
+fn single_node_dump_seeds_every_node() {
+    let tree = forked_tree(4);
+    let dump = SimulationExport { nodes: vec![TreeExport::new(4, &tree)], delay: vec![] };
+    assert_eq!(dump.tree_for(0).unwrap().tip, tree.tip.digest());
+    let two = SimulationExport { nodes: vec![TreeExport::new(4, &tree), TreeExport::new(5, &tree)], delay: vec![] };
+    assert!(two.tree_for(0).is_none());
+}
+
+#[test]
+fn moving_to_the_exported_tip_is_a_reorg() {
+    let mut tree = BlockTree::new(0, EventBus::default());
+    let genesis = SealedBlock::genesis();
+    tree.insert(genesis.clone());
+    let a = mine(&tree, 1, &genesis);
+    let b = mine(&tree, 2, &genesis);
+    tree.insert(b.clone());
+    tree.insert(a.clone());
+    let dump = TreeExport { blocks: vec![genesis.clone(), a.clone(), b.clone()], ..TreeExport::new(0, &tree) };
+    let events = EventBus::default();
+    let published = events.subscribe();
+    let mut fresh = BlockTree::new(0, events);
+    dump.load_into(&mut fresh);
+    assert_eq!(fresh.tip, b);
+    assert_eq!(fresh.reorgs.len(), 1);
+    assert_eq!(fresh.reorgs[0].abandoned, vec![a.digest().to_string()]);
+    let last_tip = published.try_iter().filter_map(|record| match record.event {
+        Event::TipChanged { hash, .. } => Some(hash),
+        _ => None,
+    }).last();
+    assert_eq!(last_tip, Some(b.digest().to_string()));
+}
@@ -0,0 +1,50 @@
+//! The light client has to pick the same tip as a full node that saw the same blocks.
+
+mod common;
+
+use crossterm_blockchain_dashboard::block::SealedBlock;
+use crossterm_blockchain_dashboard::block_tree::BlockTree;
+use crossterm_blockchain_dashboard::light_client::HeaderTree;
+use crossterm_blockchain_dashboard::EventBus;
+use common::mine;
+
+/// Tips of a full and a light node fed `blocks` in this order.
+fn tips(blocks: &[&SealedBlock]) -> (String, String) {
+    let mut full = BlockTree::new(0, EventBus::default());
+    let mut light = HeaderTree::default();
+    for block in blocks {
+        full.insert((*block).clone());
+        light.insert(block.block().header());
+    }
+    (full.tip.digest().to_string(), light.tip.digest().to_string())
+}
+
+#[test]
+fn held_back_siblings_keep_their_arrival_order() {
+    let genesis = SealedBlock::genesis();
+    let mut scratch = BlockTree::new(0, EventBus::default());
+    scratch.insert(genesis.clone());
+    let b = mine(&scratch, 0, &genesis);
+    scratch.insert(b.clone());
+    let c1 = mine(&scratch, 1, &b);
+    let c2 = mine(&scratch, 2, &b);
+    let (full, light) = tips(&[&genesis, &c1, &c2, &b]);
+    assert_eq!(full, c1.digest().to_string());
+    assert_eq!(light, full);
+}
+
+#[test]
+fn connected_sibling_does_not_take_the_tip_of_an_earlier_orphan() {
+    let genesis = SealedBlock::genesis();
+    let mut scratch = BlockTree::new(0, EventBus::default());
+    scratch.insert(genesis.clone());
+    let a1 = mine(&scratch, 1, &genesis);
+    let b1 = mine(&scratch, 0, &genesis);
+    scratch.insert(a1.clone());
+    scratch.insert(b1.clone());
+    let a2 = mine(&scratch, 1, &a1);
+    let b2 = mine(&scratch, 2, &b1);
+    let (full, light) = tips(&[&genesis, &b2, &a1, &a2, &b1]);
+    assert_eq!(full, b2.digest().to_string());
+    assert_eq!(light, full);
+}
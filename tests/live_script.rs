@@ -0,0 +1,23 @@
+//! The script served on `dashboard?live=1` lives in a Rust string, where an escape meant for
+//! JavaScript is easy to lose.
+
+use std::process::Command;
+use crossterm_blockchain_dashboard::server::live_script;
+
+#[test]
+fn no_string_literal_spans_lines() {
+    for (i, line) in live_script(&[0, 1, 2]).lines().enumerate() {
+        let code = line.split("//").next().unwrap();
+        assert_eq!(code.matches('"').count() % 2, 0, "unterminated string on line {}: {}", i + 1, line);
+    }
+}
+
+#[test]
+#[ignore = "needs node on the PATH"]
+fn script_parses() {
+    let path = std::env::temp_dir().join(format!("live-script-{}.js", std::process::id()));
+    std::fs::write(&path, live_script(&[0, 1, 2])).unwrap();
+    let output = Command::new("node").arg("--check").arg(&path).output().expect("node not found");
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
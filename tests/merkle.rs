@@ -0,0 +1,50 @@
+//! Merkle roots and inclusion proofs over transaction digests.
+
+use crossterm_blockchain_dashboard::merkle::{MerkleTree, Proof};
+
+fn leaves(n: u8) -> Vec<Vec<u8>> {
+    (0..n).map(|i| vec![i; 32]).collect()
+}
+
+#[test]
+fn every_leaf_proves_for_any_count() {
+    for n in 1..=9 {
+        let leaves = leaves(n);
+        let tree = MerkleTree::new(&leaves);
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            assert!(proof.verify(&tree.root(), leaf), "leaf {} of {}", index, n);
+            // but not at another place, nor for another leaf
+            let moved = Proof { index: (index + 1) % leaves.len(), ..proof.clone() };
+            assert!(n == 1 || !moved.verify(&tree.root(), leaf), "leaf {} of {} moved", index, n);
+            assert!(!proof.verify(&tree.root(), &[0xff; 32]), "other leaf at {} of {}", index, n);
+        }
+        assert_eq!(tree.proof(leaves.len()), None);
+    }
+}
+
+#[test]
+fn odd_leaf_is_not_duplicated() {
+    let mut three = leaves(3);
+    let root = MerkleTree::new(&three).root();
+    three.push(three[2].clone());
+    assert_ne!(MerkleTree::new(&three).root(), root);
+}
+
+#[test]
+fn inner_node_is_not_a_leaf() {
+    let leaves = leaves(4);
+    let tree = MerkleTree::new(&leaves);
+    // the hash of the first pair, with the second pair as its only sibling
+    let left = MerkleTree::new(&leaves[..2]).root();
+    let right = MerkleTree::new(&leaves[2..]).root();
+    let forged = Proof { index: 0, leaves: 2, siblings: vec![right] };
+    assert!(!forged.verify(&tree.root(), &left));
+}
+
+#[test]
+fn empty_tree() {
+    let tree = MerkleTree::new(&[]);
+    assert_eq!(tree.root(), vec![0; 32]);
+    assert_eq!(tree.proof(0), None);
+}